@@ -1,16 +1,22 @@
 extern crate nalgebra as na;
 use std::fs;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::ops::Index;
 
 use crate::lerp::MonomialLerp;
-use crate::kdtree::CKDTree;
-use std::sync::Arc;
+use crate::kdforest::KdForest;
+use crate::kdtree::{json_string, run_batch, AxisBox, CKDTree, KdPoint, KdTree, NearestHint, QueryTrace, SplitRule};
+use crate::metric::{Angular, Euclidean, Manhattan, Metric, SquaredEuclidean, TrueMetric};
+use crate::snapshot::{fnv1a_64, SnapshotReader, SnapshotWriter};
+use crate::spatial_index::{LinearScan, MetricIndex, SelfCheck, SpatialIndex};
+use crate::vptree::VpTree;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
 #[derive(Debug)]
 struct FehUnit {
     m_name: String,
     m_character: String,
+    m_weapon: String, // empty when unknown
     m_stats: na::Vector5<f32>
 }
 
@@ -19,10 +25,16 @@ impl FehUnit {
         return FehUnit {
             m_name: name,
             m_character: character,
+            m_weapon: String::new(),
             m_stats: stats
         };
     }
 
+    fn with_weapon(mut self, weapon: String) -> Self {
+        self.m_weapon = weapon;
+        return self;
+    }
+
     // Returns a reference to a FehUnit's stats, which is internally stored
     // as an na::Vector5<f32>
     fn get_stats(&self) -> &na::Vector5<f32> {
@@ -37,6 +49,10 @@ impl FehUnit {
         return self.m_character.as_str();
     }
 
+    fn get_weapon(&self) -> &str {
+        return self.m_weapon.as_str();
+    }
+
     fn describe(&self) -> () {
         println!("[\nUnit Name: {}\nCharacter: {}\nStats: {}", self.m_name, self.m_character, self.m_stats);
     }
@@ -44,6 +60,11 @@ impl FehUnit {
     fn as_arc(self) -> Arc<Self> {
         return Arc::new(self);
     }
+
+    // Returns a copy of a FehUnit's stats laid out as the coordinates of a point in a FehKDTree
+    fn get_coords(&self) -> [f32; 5] {
+        return self.m_stats.into();
+    }
 }
 
 // for compatability w/ KDTREE
@@ -54,21 +75,464 @@ impl Index<usize> for FehUnit {
     }
 }
 
+// lets a KD-tree hold FehUnits (or pointers to them) directly
+impl KdPoint for FehUnit {
+    type Scalar = f32;
+
+    fn dimensions(&self) -> usize {
+        return self.m_stats.len();
+    }
+
+    fn coord(&self, axis: usize) -> f32 {
+        return self.m_stats[axis];
+    }
+}
+
+/** The metric every FehManager query searches with and every distance it reports is measured in, except for those the
+ `VP_TREE` backend answers under its own `FehVpMetric`. */
+pub type FehMetric = Euclidean;
+
+/** Names of the stat axes of a FehUnit, in the order they are indexed. */
+pub const STAT_NAMES: [&str; 5] = ["HP", "Atk", "Spd", "Def", "Res"];
+
+/** The number of steps an interpolation between two units takes, not counting the last unit itself. */
+pub const LERP_STEPS: usize = 10;
+
+/** The most steps a client may ask an interpolation or a sweep to take. */
+pub const MAX_LERP_STEPS: usize = LERP_STEPS * 100;
+
+/** The most units that can be added to a `FehKDTree` while the server runs. */
+pub const MAX_ADDED_UNITS: usize = 4096;
+
+/** The stats of a unit as the coordinates of a point in a FehKDTree, tagged with the unit's payload index. */
+#[derive(Debug, Clone, Copy)]
+struct FehCoordPoint([f32; 5], usize);
+
+impl KdPoint for FehCoordPoint {
+    type Scalar = f32;
+
+    fn dimensions(&self) -> usize {
+        return 5;
+    }
+
+    fn coord(&self, axis: usize) -> f32 {
+        return self.0[axis];
+    }
+}
+
+/** The stats of a unit in tenths of a stat point (`LERP_STEPS` to the point), as whole numbers, tagged with the unit's
+ payload index. Stats are whole numbers, so every step of an interpolation between two units lands on whole numbers in
+ these units, and its distances to the units are measured exactly. */
+#[derive(Debug, Clone, Copy)]
+struct FehStatPoint([i16; 5], usize);
+
+impl FehStatPoint {
+    /** Scales `stats` to the units of a `FehStatPoint`. */
+    fn scale(stats: &na::Vector5<f32>) -> [i16; 5] {
+        return stats.map(|stat| (stat * LERP_STEPS as f32).round() as i16).into();
+    }
+}
+
+impl KdPoint for FehStatPoint {
+    type Scalar = i16;
+
+    fn dimensions(&self) -> usize {
+        return 5;
+    }
+
+    fn coord(&self, axis: usize) -> i16 {
+        return self.0[axis];
+    }
+}
+
+/** Restricts which units an interpolation step is allowed to land on. Every option is off by default. */
+#[derive(Debug, Default, Clone)]
+pub struct LerpFilter {
+    m_exclude_endpoints: bool,
+    m_exclude_same_character: bool,
+    m_weapon: Option<String>
+}
+
+impl LerpFilter {
+    pub fn new() -> Self {
+        return LerpFilter::default();
+    }
+
+    /** Never land on the start or end unit of the interpolation. */
+    pub fn exclude_endpoints(mut self, exclude: bool) -> Self {
+        self.m_exclude_endpoints = exclude;
+        return self;
+    }
+
+    /** Never land on a unit of the same character as the start or end unit (alts included). */
+    pub fn exclude_same_character(mut self, exclude: bool) -> Self {
+        self.m_exclude_same_character = exclude;
+        return self;
+    }
+
+    /** Only land on units wielding the given weapon type. */
+    pub fn only_weapon(mut self, weapon: Option<String>) -> Self {
+        self.m_weapon = weapon;
+        return self;
+    }
+
+    /** Checks whether none of the options are on, i.e. every unit is accepted. */
+    pub fn is_empty(&self) -> bool {
+        return !self.m_exclude_endpoints && !self.m_exclude_same_character && self.m_weapon.is_none();
+    }
+
+    /** Checks whether `unit` may be landed on when interpolating from `start` to `end`. */
+    fn accepts(&self, unit: &FehUnit, start: &FehUnit, end: &FehUnit) -> bool {
+        let is_endpoint = || unit.get_name() == start.get_name() || unit.get_name() == end.get_name();
+        let same_character = || unit.get_character() == start.get_character() || unit.get_character() == end.get_character();
+        let wrong_weapon = || self.m_weapon.as_ref().is_some_and(|weapon| unit.get_weapon() != weapon.as_str());
+        return !(self.m_exclude_endpoints && is_endpoint()) && !(self.m_exclude_same_character && same_character()) && !wrong_weapon();
+    }
+}
+
 #[derive(Debug)]
 pub struct FehManager {
     m_unit_map: BTreeMap<String, Arc<FehUnit>>,
+    m_metric: FehMetric,
     // m_unit_kdtree: Option<CKDTree<'a,f32,FehUnit>>
 }
 
-pub struct FehKDTree(CKDTree<Arc<FehUnit>>);
+/** Which index answers the nearest, k-nearest, radius and range queries of a `FehKDTree`. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FehBackend {
+    KD_TREE = 0,     // the tree
+    LINEAR_SCAN = 1, // a linear scan over every unit, faster than the tree for small unit lists
+    SELF_CHECK = 2,  // the tree, with every answer checked against the linear scan and disagreements logged
+    VP_TREE = 3      // a vantage point tree, which only relies on the metric obeying the triangle inequality, searching under its `FehVpMetric`
+}
+
+impl FehBackend {
+    /** Unit lists shorter than this are searched with a linear scan by default. */
+    pub const LINEAR_SCAN_LIMIT: usize = 64;
+
+    /** The backend suited to a unit list of `unit_count` units. */
+    pub fn for_size(unit_count: usize) -> Self {
+        return if unit_count < Self::LINEAR_SCAN_LIMIT { FehBackend::LINEAR_SCAN } else { FehBackend::KD_TREE };
+    }
+
+    /** Parses a backend name as given on the command line or in the environment ("kd_tree", "linear_scan", "self_check"
+     or "vp_tree"). */
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "kd_tree" => Some(FehBackend::KD_TREE),
+            "linear_scan" => Some(FehBackend::LINEAR_SCAN),
+            "self_check" => Some(FehBackend::SELF_CHECK),
+            "vp_tree" => Some(FehBackend::VP_TREE),
+            _ => None
+        };
+    }
+}
+
+/** The metric the `VP_TREE` backend is built with and searches under. A VP-tree only needs a `TrueMetric`, so besides
+ the straight line distance it can compare units by how their stats are spread, whatever their totals. */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FehVpMetric {
+    #[default]
+    EUCLIDEAN = 0, // the same metric as every other backend
+    MANHATTAN = 1,
+    ANGULAR = 2    // the angle between stat vectors: units with the same spread at different totals lie at distance 0
+}
+
+impl FehVpMetric {
+    /** Parses a metric name as given in the environment ("euclidean", "manhattan" or "angular"). */
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "euclidean" => Some(FehVpMetric::EUCLIDEAN),
+            "manhattan" => Some(FehVpMetric::MANHATTAN),
+            "angular" => Some(FehVpMetric::ANGULAR),
+            _ => None
+        };
+    }
+}
+
+impl Metric for FehVpMetric {
+    fn distance<A: KdPoint<Scalar = f32> + ?Sized, B: KdPoint<Scalar = f32> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> f32 {
+        return match self {
+            FehVpMetric::EUCLIDEAN => Euclidean.distance(p1, p2, dimensions),
+            FehVpMetric::MANHATTAN => Manhattan.distance(p1, p2, dimensions),
+            FehVpMetric::ANGULAR => Angular.distance(p1, p2, dimensions)
+        };
+    }
+
+    fn axis_distance(&self, axis: usize, delta: f32) -> f32 {
+        return match self {
+            FehVpMetric::EUCLIDEAN => Euclidean.axis_distance(axis, delta),
+            FehVpMetric::MANHATTAN => Metric::<f32>::axis_distance(&Manhattan, axis, delta),
+            FehVpMetric::ANGULAR => Angular.axis_distance(axis, delta)
+        };
+    }
+}
+
+impl TrueMetric for FehVpMetric {}
+
+/** The units of a FehManager indexed by their stats. The tree holds the stats inline and refers to each unit by its
+ position in `m_units`; so do the linear scan and the VP-tree, whose ids are those same positions. Nearest, k-nearest,
+ radius and range queries, batched or not, go to whichever index `m_backend` picks, while the queries only the KD-tree
+ supports (warm starts, filters, segments, traces and incremental scans) always go to it. The VP-tree searches under the
+ `FehVpMetric` it was built with rather than the metric passed to each query. Interpolations are searched in the stat
+ tree, which holds the stats as `FehStatPoint`s so that ties are exact. Units added while the server runs take the
+ payload indices after those of `m_units` and are indexed in `m_added`, whose answers every unit query merges with
+ those of the indices over the unit list; the diagnostics only describe the tree. */
+pub struct FehKDTree {
+    m_tree: KdTree<5>,
+    m_stat_tree: CKDTree<FehStatPoint>,
+    m_scan: LinearScan<[f32; 5]>,
+    m_vp_tree: VpTree<[f32; 5], FehVpMetric>,
+    m_units: Vec<Arc<FehUnit>>,
+    m_backend: FehBackend,
+    m_disagreements: Mutex<VecDeque<String>>,
+    m_added_units: Vec<OnceLock<FehUnit>>, // the units added so far, by payload index past `m_units`; set once, so they can be lent out without a lock
+    m_added: RwLock<FehAdded>
+}
+
+/** The units added to a `FehKDTree` while the server runs, indexed the same two ways as the unit list: by coordinates,
+ and as `FehStatPoint`s for interpolations. Each is a forest, so it stays balanced however many units arrive. */
+#[derive(Debug)]
+struct FehAdded {
+    m_coords: KdForest<FehCoordPoint>,
+    m_stats: KdForest<FehStatPoint>,
+    m_names: BTreeMap<String, usize> // payload index of each added unit by name
+}
+
 impl FehKDTree {
+    /** Bulk builds a balanced tree over every unit in `man`, splitting each node along the stat with the highest
+     variance among the units below it. The backend is picked by the number of units. */
     pub fn construct_kdtree(man: &FehManager) -> Self {
-        let mut unit_tree: CKDTree<Arc<FehUnit>> = CKDTree::new(5);
-        for unit_data in man.m_unit_map.iter() {
-            unit_tree.push_wrapped(unit_data.1.clone());
-        }
+        let units: Vec<Arc<FehUnit>> = man.m_unit_map.values().cloned().collect();
+        let coords: Vec<[f32; 5]> = units.iter().map(|unit| unit.get_coords()).collect();
+        return Self::from_parts(KdTree::from_median(&coords, SplitRule::MAX_VARIANCE), units);
+    }
+
+    /** Pairs a built tree with the units its payload indices refer to. */
+    fn from_parts(tree: KdTree<5>, units: Vec<Arc<FehUnit>>) -> Self {
+        let coords: Vec<[f32; 5]> = units.iter().map(|unit| unit.get_coords()).collect();
+        let (scan, vp_tree) = (LinearScan::new(5, coords.clone()), VpTree::new(5, coords, FehVpMetric::default()));
+        let stat_points: Vec<FehStatPoint> = units.iter().enumerate().map(|(payload, unit)| FehStatPoint(FehStatPoint::scale(unit.get_stats()), payload)).collect();
+        let stat_tree: CKDTree<FehStatPoint> = CKDTree::from_median(5, stat_points, SplitRule::MAX_VARIANCE);
+        let backend: FehBackend = FehBackend::for_size(units.len());
+        return FehKDTree {
+            m_tree: tree, m_stat_tree: stat_tree, m_scan: scan, m_vp_tree: vp_tree, m_units: units, m_backend: backend, m_disagreements: Mutex::new(VecDeque::new()),
+            m_added_units: (0..MAX_ADDED_UNITS).map(|_| OnceLock::new()).collect(),
+            m_added: RwLock::new(FehAdded { m_coords: KdForest::new(5, SplitRule::MAX_VARIANCE), m_stats: KdForest::new(5, SplitRule::MAX_VARIANCE), m_names: BTreeMap::new() })
+        };
+    }
+
+    /** Switches the index that answers nearest, k-nearest, radius and range queries. */
+    pub fn with_backend(mut self, backend: FehBackend) -> Self {
+        self.m_backend = backend;
+        return self;
+    }
+
+    pub fn backend(&self) -> FehBackend {
+        return self.m_backend;
+    }
+
+    /** Rebuilds the VP-tree to search under `metric`. */
+    pub fn with_vp_metric(mut self, metric: FehVpMetric) -> Self {
+        let coords: Vec<[f32; 5]> = self.m_units.iter().map(|unit| unit.get_coords()).collect();
+        self.m_vp_tree = VpTree::new(5, coords, metric);
+        return self;
+    }
+
+    /** Returns the latest disagreements between the tree and the linear scan logged in `SELF_CHECK` mode, oldest first. At
+     most `SelfCheck::LOG_LIMIT` are kept. */
+    pub fn disagreements(&self) -> Vec<String> {
+        return self.m_disagreements.lock().unwrap().iter().cloned().collect();
+    }
 
-        return FehKDTree(unit_tree);
+    /** Returns the number of units added to this tree with `FehManager::add_unit`. */
+    pub fn added_count(&self) -> usize {
+        return self.m_added.read().unwrap().m_names.len();
+    }
+
+    /** Indexes `unit` under the next payload index past the units added so far. Fails once `MAX_ADDED_UNITS` have been
+     added, or if an added unit already has its name. */
+    fn add(&self, unit: FehUnit) -> std::result::Result<(), &'static str> {
+        let mut added = self.m_added.write().unwrap();
+        let slot: usize = added.m_names.len();
+        if slot == MAX_ADDED_UNITS { return Err("no more units can be added"); }
+        if added.m_names.contains_key(unit.get_name()) { return Err("a unit with that name already exists"); }
+
+        let payload: usize = self.m_units.len() + slot;
+        added.m_coords.push(FehCoordPoint(unit.get_coords(), payload));
+        added.m_stats.push(FehStatPoint(FehStatPoint::scale(unit.get_stats()), payload));
+        added.m_names.insert(unit.get_name().to_owned(), payload);
+        _ = self.m_added_units[slot].set(unit);
+        return Ok(());
+    }
+
+    /** Returns the added unit named `name`, if there is one. */
+    fn added_unit(&self, name: &str) -> Option<&FehUnit> {
+        let payload: usize = *self.m_added.read().unwrap().m_names.get(name)?;
+        return Some(self.unit(payload));
+    }
+
+    /** Returns the unit a payload index refers to, whether from the unit list or added. */
+    fn unit(&self, payload: usize) -> &FehUnit {
+        return match payload.checked_sub(self.m_units.len()) {
+            Some(slot) => self.m_added_units[slot].get().unwrap(),
+            None => self.m_units[payload].as_ref()
+        };
+    }
+
+    /** Keeps the closer of an answer from the indices over the unit list and one from the added units, the former on a tie. */
+    fn closer(found: Option<(usize, f32)>, added: Option<(usize, f32)>) -> Option<(usize, f32)> {
+        return match (found, added) {
+            (Some(found), Some(added)) => Some(if added.1 < found.1 { added } else { found }),
+            (found, added) => found.or(added)
+        };
+    }
+
+    /** Merges the answers from the added units into those from the unit list, closest first (the unit list's first among
+     ties), keeping at most `limit`. */
+    fn merged(mut found: Vec<(usize, f32)>, added: Vec<(usize, f32)>, limit: usize) -> Vec<(usize, f32)> {
+        found.extend(added);
+        found.sort_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+        found.truncate(limit);
+        return found;
+    }
+
+    /** The payload index of the added unit closest to `point`, with its distance, measured as the backend measures it. */
+    fn added_nearest(&self, point: &[f32; 5], metric: &FehMetric) -> Option<(usize, f32)> {
+        let added = self.m_added.read().unwrap();
+        let found = match self.m_backend {
+            FehBackend::VP_TREE => added.m_coords.nearest_neighbor(point, self.m_vp_tree.metric()),
+            _ => added.m_coords.nearest_neighbor(point, metric)
+        };
+
+        return found.map(|(unit, dist)| (unit.1, dist));
+    }
+
+    /** Swaps the payload index of every (payload, distance) pair for the unit it refers to. */
+    fn units_with_dist(&self, found: Vec<(usize, f32)>) -> Vec<(&FehUnit, f32)> {
+        return found.into_iter().map(|(payload, dist)| (self.unit(payload), dist)).collect();
+    }
+
+    /** The tree checked against the linear scan, logging into `m_disagreements`. Its ids are tree nodes. */
+    fn checked(&self) -> SelfCheck<'_, KdTree<5>, LinearScan<[f32; 5]>> {
+        return SelfCheck::new(&self.m_tree, &self.m_scan, &self.m_disagreements);
+    }
+
+    /** Swaps the tree node of every (node, distance) pair for its payload index. */
+    fn node_payloads(&self, found: Vec<(usize, f32)>) -> Vec<(usize, f32)> {
+        return found.into_iter().map(|(node, dist)| (self.m_tree.payload_of(node), dist)).collect();
+    }
+
+    /** The payload index of the unit closest to `point` under the backend, with its distance. */
+    fn nearest(&self, point: &[f32; 5], metric: &FehMetric) -> Option<(usize, f32)> {
+        let found: Option<(usize, f32)> = match self.m_backend {
+            FehBackend::KD_TREE => self.m_tree.nearest_neighbor(point, metric),
+            FehBackend::LINEAR_SCAN => self.m_scan.query_nearest(point, metric),
+            FehBackend::SELF_CHECK => self.checked().query_nearest(point, metric).map(|(node, dist)| (self.m_tree.payload_of(node), dist)),
+            FehBackend::VP_TREE => self.m_vp_tree.query_nearest(point, self.m_vp_tree.metric())
+        };
+
+        return Self::closer(found, self.added_nearest(point, metric));
+    }
+
+    /** The payload index of the unit closest to each of `points` under the backend, with its distance, spreading the
+     searches over `threads` worker threads. Results are in the same order as `points`. */
+    fn nearest_batch(&self, points: &[[f32; 5]], metric: &FehMetric, threads: usize) -> Vec<Option<(usize, f32)>> {
+        return match self.m_backend {
+            FehBackend::KD_TREE => self.m_tree.nearest_batch(points, metric, threads).into_iter().zip(points)
+                .map(|(found, point)| Self::closer(found, self.added_nearest(point, metric))).collect(),
+            _ => run_batch(points, threads, |point: &[f32; 5]| self.nearest(point, metric))
+        };
+    }
+
+    /** The payload indices of the `k` units closest to `point` under the backend, closest first. */
+    fn k_nearest(&self, point: &[f32; 5], k: usize, metric: &FehMetric) -> Vec<(usize, f32)> {
+        let found: Vec<(usize, f32)> = match self.m_backend {
+            FehBackend::KD_TREE => self.m_tree.k_nearest(point, k, metric),
+            FehBackend::LINEAR_SCAN => self.m_scan.query_k_nearest(point, k, metric),
+            FehBackend::SELF_CHECK => self.node_payloads(self.checked().query_k_nearest(point, k, metric)),
+            FehBackend::VP_TREE => self.m_vp_tree.query_k_nearest(point, k, self.m_vp_tree.metric())
+        };
+
+        let added = self.m_added.read().unwrap();
+        let added_found = match self.m_backend {
+            FehBackend::VP_TREE => added.m_coords.k_nearest(point, k, self.m_vp_tree.metric()),
+            _ => added.m_coords.k_nearest(point, k, metric)
+        };
+
+        return Self::merged(found, added_found.into_iter().map(|(unit, dist)| (unit.1, dist)).collect(), k);
+    }
+
+    /** The payload indices of every unit within `radius` of `point` under the backend, closest first. */
+    fn within_radius(&self, point: &[f32; 5], radius: f32, metric: &FehMetric) -> Vec<(usize, f32)> {
+        let found: Vec<(usize, f32)> = match self.m_backend {
+            FehBackend::KD_TREE => self.m_tree.within_radius(point, radius, metric),
+            FehBackend::LINEAR_SCAN => self.m_scan.query_radius(point, radius, metric),
+            FehBackend::SELF_CHECK => self.node_payloads(self.checked().query_radius(point, radius, metric)),
+            FehBackend::VP_TREE => self.m_vp_tree.query_radius(point, radius, self.m_vp_tree.metric())
+        };
+
+        let added = self.m_added.read().unwrap();
+        let added_found = match self.m_backend {
+            FehBackend::VP_TREE => added.m_coords.within_radius(point, radius, self.m_vp_tree.metric()),
+            _ => added.m_coords.within_radius(point, radius, metric)
+        };
+
+        return Self::merged(found, added_found.into_iter().map(|(unit, dist)| (unit.1, dist)).collect(), usize::MAX);
+    }
+
+    /** The payload indices of every unit inside `bounds` under the backend. */
+    fn range_search(&self, bounds: &AxisBox) -> Vec<usize> {
+        let mut found: Vec<usize> = match self.m_backend {
+            FehBackend::KD_TREE => self.m_tree.range_search(bounds),
+            FehBackend::LINEAR_SCAN => self.m_scan.query_range(bounds),
+            FehBackend::SELF_CHECK => self.checked().query_range(bounds).into_iter().map(|node| self.m_tree.payload_of(node)).collect(),
+            FehBackend::VP_TREE => self.m_vp_tree.query_range(bounds)
+        };
+
+        found.extend(self.m_added.read().unwrap().m_coords.range_search(bounds).into_iter().map(|unit| unit.1));
+        return found;
+    }
+
+    /** The payload index of the unit farthest from `point` among those `accepts` takes, with its distance. Like the other
+     queries only the KD-tree answers, it searches the tree rather than the backend. */
+    fn farthest_filtered(&self, point: &[f32; 5], metric: &FehMetric, accepts: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        let found: Option<(usize, f32)> = self.m_tree.farthest_neighbor_filtered(point, metric, &accepts);
+        let added: Option<(usize, f32)> = self.m_added.read().unwrap().m_coords.farthest_neighbor_filtered(point, metric, |unit| accepts(unit.1)).map(|(unit, dist)| (unit.1, dist));
+        return match (found, added) {
+            (Some(found), Some(added)) => Some(if added.1 > found.1 { added } else { found }),
+            (found, added) => found.or(added)
+        };
+    }
+
+    /** The payload index of the unit closest to `point` among those `accepts` takes, with its distance, searching the tree
+     lazily in increasing distance until one is accepted. */
+    fn nearest_accepted(&self, point: &[f32; 5], metric: &FehMetric, accepts: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        let found: Option<(usize, f32)> = self.m_tree.nearest_iter(point, metric).find(|(payload, _)| accepts(*payload));
+        let added: Option<(usize, f32)> = self.m_added.read().unwrap().m_coords.nearest_neighbor_filtered(point, metric, |unit| accepts(unit.1)).map(|(unit, dist)| (unit.1, dist));
+        return Self::closer(found, added);
+    }
+
+    /** The payload indices of the `k` units closest to the segment from `a` to `b` among those `accepts` takes, closest
+     first, each with its distance to the segment and the parameter t of its closest approach. */
+    fn nearest_to_segment_filtered(&self, a: &[f32; 5], b: &[f32; 5], k: usize, accepts: impl Fn(usize) -> bool) -> Vec<(usize, f32, f32)> {
+        let mut found: Vec<(usize, f32, f32)> = self.m_tree.nearest_to_segment_filtered(a, b, k, &accepts);
+        found.extend(self.m_added.read().unwrap().m_coords.nearest_to_segment_filtered(a, b, k, |unit| accepts(unit.1)).into_iter().map(|(unit, dist, t)| (unit.1, dist, t)));
+        found.sort_by(|(_, d1, _), (_, d2, _)| d1.total_cmp(d2));
+        found.truncate(k);
+        return found;
+    }
+
+    /** The payload indices of every unit `accepts` takes at exactly the closest distance to `point` in the stat tree and
+     among the added units, with their squared distances, along with a `QueryTrace` of the stat tree's search. */
+    fn stat_ties_filtered(&self, point: &[i16; 5], accepts: impl Fn(usize) -> bool) -> (Vec<(usize, i64)>, QueryTrace) {
+        let (found, trace) = self.m_stat_tree.nearest_ties_filtered_traced(point, 0, &SquaredEuclidean, |unit| accepts(unit.1));
+        let mut ties: Vec<(usize, i64)> = found.into_iter().map(|(unit, dist)| (unit.1, dist)).collect();
+        ties.extend(self.m_added.read().unwrap().m_stats.nearest_ties_filtered(point, 0, &SquaredEuclidean, |unit| accepts(unit.1)).into_iter().map(|(unit, dist)| (unit.1, dist)));
+
+        let nearest: i64 = ties.iter().map(|(_, dist)| *dist).min().unwrap_or(0);
+        ties.retain(|(_, dist)| *dist == nearest);
+        return (ties, trace);
     }
 }
 
@@ -80,11 +544,12 @@ impl FehKDTree {
 // }
 
 impl FehManager {
-    fn populate_tuple<'temp_tuple, 'from_file>(stream_net: &'temp_tuple mut (&'from_file str, &'from_file str, na::Vector5<f32>), comma_slice: &'from_file str, comma_cnt: usize) {
+    fn populate_tuple<'temp_tuple, 'from_file>(stream_net: &'temp_tuple mut (&'from_file str, &'from_file str, na::Vector5<f32>, &'from_file str), comma_slice: &'from_file str, comma_cnt: usize) {
         //println!("name: {}, char: {}, comma_slice: {}", stream_net.0, stream_net.1, comma_slice);
         match comma_cnt {
             2 => { stream_net.0 = comma_slice; },
             3 => { stream_net.1 = comma_slice; },
+            6 => { stream_net.3 = comma_slice; }, // weapon type
             30..=34 => {
                 let actual_idx: usize = comma_cnt - 30;
                 stream_net.2[actual_idx] = comma_slice.parse::<f32>().unwrap();
@@ -93,7 +558,7 @@ impl FehManager {
         };
     }
 
-    fn populate_unit_map<'a,'b>(mut self, file_str: &'a String, mut stream_net: &'b mut (&'a str, &'a str, na::Vector5<f32>)) -> Self {
+    fn populate_unit_map<'a,'b>(mut self, file_str: &'a String, mut stream_net: &'b mut (&'a str, &'a str, na::Vector5<f32>, &'a str)) -> Self {
         let mut file_iter = file_str.as_bytes().iter();
         let mut offset = 0;
         while *file_iter.next().unwrap() != b'\n' { offset += 1; } // skip the first row
@@ -117,7 +582,7 @@ impl FehManager {
                 b'\n' => {
                     println!("row unit: {:?}", stream_net);
                     let name_slice: String = stream_net.0.to_string();
-                    let new_unit: FehUnit = FehUnit::new(stream_net.0.to_string(), stream_net.1.to_string(), stream_net.2);
+                    let new_unit: FehUnit = FehUnit::new(stream_net.0.to_string(), stream_net.1.to_string(), stream_net.2).with_weapon(stream_net.3.to_string());
                     self.m_unit_map.insert(name_slice, Arc::new(new_unit));
 
                     comma_count = 0;
@@ -131,12 +596,100 @@ impl FehManager {
     }
 
     pub fn init(fpath: &str) -> std::result::Result<FehManager, &'static str> {
-        let man: FehManager = FehManager { m_unit_map: BTreeMap::new(), };
+        let file_str = fs::read_to_string(fpath).expect("The path shouldn't be wrong here. Nothing abnormal about the file either.");
+        return std::result::Result::Ok(FehManager::from_csv(&file_str));
+    }
+
+    /** Parses the contents of a unit list CSV into a FehManager */
+    fn from_csv(file_str: &String) -> FehManager {
+        let man: FehManager = FehManager { m_unit_map: BTreeMap::new(), m_metric: Euclidean };
 
         // structures
-        let mut stream_net: (&str, &str, na::Vector5<f32>) = ("", "", na::Vector5::zeros());
-        let file_str = fs::read_to_string(fpath).expect("The path shouldn't be wrong here. Nothing abnormal about the file either.");
-        return std::result::Result::Ok(man.populate_unit_map(&file_str, &mut stream_net));
+        let mut stream_net: (&str, &str, na::Vector5<f32>, &str) = ("", "", na::Vector5::zeros(), "");
+        return man.populate_unit_map(file_str, &mut stream_net);
+    }
+
+    /** Loads the units and their tree from the snapshot at `snapshot_path`, provided it was made from the current contents
+     of the CSV at `csv_path`. When the snapshot is missing, stale or corrupt, falls back to parsing the CSV and building
+     the tree, then writes a fresh snapshot for the next startup. */
+    pub fn init_with_snapshot(csv_path: &str, snapshot_path: &str) -> std::result::Result<(FehManager, FehKDTree), &'static str> {
+        let csv_bytes: Vec<u8> = fs::read(csv_path).map_err(|_| "the unit list could not be read")?;
+        let source_hash: u64 = fnv1a_64(&csv_bytes);
+
+        match FehManager::load_snapshot(snapshot_path, source_hash) {
+            Ok(loaded) => { return Ok(loaded); },
+            Err(reason) => { println!("Not using snapshot {}: {}", snapshot_path, reason); }
+        };
+
+        let file_str: String = String::from_utf8(csv_bytes).map_err(|_| "the unit list is not valid UTF-8")?;
+        let man: FehManager = FehManager::from_csv(&file_str);
+        let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+        if let Err(reason) = man.save_snapshot(&tree, snapshot_path, source_hash) {
+            println!("Could not write snapshot {}: {}", snapshot_path, reason);
+        }
+
+        return Ok((man, tree));
+    }
+
+    /** Writes the unit table along with the node array of `tree` to a snapshot at `path`. Tree nodes refer to units by
+     their position in the table. `source_hash` fingerprints the CSV the units were parsed from. */
+    pub fn save_snapshot(&self, tree: &FehKDTree, path: &str, source_hash: u64) -> std::result::Result<(), &'static str> {
+        let mut writer: SnapshotWriter = SnapshotWriter::new();
+        let mut unit_indices: BTreeMap<&str, usize> = BTreeMap::new();
+
+        writer.write_u32(self.m_unit_map.len() as u32);
+        for (idx, unit) in self.m_unit_map.values().enumerate() {
+            unit_indices.insert(unit.get_name(), idx);
+            writer.write_str(unit.get_name()).write_str(unit.get_character()).write_str(unit.get_weapon());
+            for stat in unit.get_stats().iter() { writer.write_f32(*stat); }
+        }
+
+        writer.write_u32(tree.m_tree.dimensions() as u32).write_u8(tree.m_tree.split_rule() as u8).write_u32(tree.m_tree.raw_nodes().count() as u32);
+        for (axis, dirs, removed, _, payload) in tree.m_tree.raw_nodes() {
+            let unit: &FehUnit = tree.unit(payload);
+            let unit_idx: usize = *unit_indices.get(unit.get_name()).ok_or("the tree holds a unit missing from the unit table")?;
+            writer.write_u32(axis as u32).write_index(dirs[0]).write_index(dirs[1]).write_u8(removed as u8).write_u32(unit_idx as u32);
+        }
+
+        return fs::write(path, writer.finish(source_hash)).map_err(|_| "the snapshot could not be written");
+    }
+
+    /** Restores the unit table and its tree from the snapshot at `path` without parsing the CSV or re-inserting any unit.
+     Fails if the snapshot is unreadable, corrupt, or was made from a CSV other than the one `source_hash` fingerprints. */
+    pub fn load_snapshot(path: &str, source_hash: u64) -> std::result::Result<(FehManager, FehKDTree), &'static str> {
+        let bytes: Vec<u8> = fs::read(path).map_err(|_| "the snapshot could not be read")?;
+        let mut reader: SnapshotReader = SnapshotReader::open(&bytes, source_hash)?;
+
+        let unit_count: usize = reader.read_u32()? as usize;
+        let mut units: Vec<Arc<FehUnit>> = Vec::with_capacity(unit_count);
+        for _ in 0..unit_count {
+            let (name, character, weapon) = (reader.read_str()?, reader.read_str()?, reader.read_str()?);
+            let mut stats: na::Vector5<f32> = na::Vector5::zeros();
+            for stat in stats.iter_mut() { *stat = reader.read_f32()?; }
+            units.push(FehUnit::new(name.to_owned(), character.to_owned(), stats).with_weapon(weapon.to_owned()).as_arc());
+        }
+
+        if reader.read_u32()? != 5 { return Err("the snapshot holds a tree over other than five stats"); }
+        let rule: SplitRule = match reader.read_u8()? {
+            0 => SplitRule::CYCLE_AXES,
+            1 => SplitRule::MAX_VARIANCE,
+            _ => { return Err("the snapshot holds an unknown split rule"); }
+        };
+
+        let node_count: usize = reader.read_u32()? as usize;
+        let mut nodes: Vec<(usize, [Option<usize>; 2], bool, [f32; 5], usize)> = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let (axis, left_idx, right_idx, removed) = (reader.read_u32()? as usize, reader.read_index()?, reader.read_index()?, reader.read_u8()? != 0);
+            let unit_idx: usize = reader.read_u32()? as usize;
+            let unit: &Arc<FehUnit> = units.get(unit_idx).ok_or("a tree node refers to a unit missing from the unit table")?;
+            nodes.push((axis, [left_idx, right_idx], removed, unit.get_coords(), unit_idx));
+        }
+
+        if !reader.is_done() { return Err("the snapshot has trailing data"); }
+
+        let tree: FehKDTree = FehKDTree::from_parts(KdTree::from_raw_nodes(rule, nodes)?, units.clone());
+        let unit_map: BTreeMap<String, Arc<FehUnit>> = units.into_iter().map(|unit| (unit.get_name().to_owned(), unit)).collect();
+        return Ok((FehManager { m_unit_map: unit_map, m_metric: Euclidean }, tree));
     }
 
     pub fn num_units(&self) -> usize {
@@ -170,48 +723,230 @@ impl FehManager {
         return self.m_unit_map.contains_key(query);
     }
 
-    fn mock_unit(stats: &na::Vector5<f32>) -> FehUnit {
-        return FehUnit::new("".to_string(), "".to_string(), stats.clone());
+    /** Checks whether `query` names a unit of the list or a unit added to `tree`. */
+    pub fn contains_unit(&self, query: &str, tree: &FehKDTree) -> bool {
+        return self.find_unit(query, tree).is_some();
+    }
+
+    /** Returns the unit named `unit_name`, looking through the unit list and then the units added to `tree`. */
+    fn find_unit<'man>(&'man self, unit_name: &str, tree: &'man FehKDTree) -> Option<&'man FehUnit> {
+        return self.m_unit_map.get(unit_name).map(|unit| unit.as_ref()).or_else(|| tree.added_unit(unit_name));
+    }
+
+    /** Adds a unit to `tree` while the server runs, without rebuilding the tree of the unit list. From then on every query
+     over `tree` can return it and take it by name. The name must not be taken by a unit of the list or an earlier added
+     unit, and at most `MAX_ADDED_UNITS` units can be added. */
+    pub fn add_unit(&self, name: &str, character: &str, weapon: &str, stats: [f32; 5], tree: &FehKDTree) -> std::result::Result<(), &'static str> {
+        if !stats.iter().all(|stat| stat.is_finite()) { return Err("unit stats must be finite numbers"); }
+        if self.contains(name) { return Err("a unit with that name already exists"); }
+        return tree.add(FehUnit::new(name.to_owned(), character.to_owned(), stats.into()).with_weapon(weapon.to_owned()));
+    }
+
+    /** Returns the unit closest to `point` along with its distance from `point`. */
+    fn closest_to<'man, 'temp>(&'man self, point: &'temp na::Vector5<f32>, tree: &'man FehKDTree) -> (&'man FehUnit, f32) {
+        let (payload, dist) = tree.nearest(&(*point).into(), &self.m_metric).unwrap();
+        return (tree.unit(payload), dist);
+    }
+
+    /** Finds the unit closest to each of `points` (and its distance), spreading the searches over `threads` worker
+     threads. Results are in the same order as `points`, `None` wherever no unit was found. */
+    pub(crate) fn closest_to_batch<'man>(&'man self, points: &[na::Vector5<f32>], threads: usize, tree: &'man FehKDTree) -> Vec<Option<(&'man FehUnit, f32)>> {
+        let queries: Vec<[f32; 5]> = points.iter().map(|point| (*point).into()).collect();
+        return tree.nearest_batch(&queries, &self.m_metric, threads).into_iter()
+            .map(|found| found.map(|(payload, dist)| (tree.unit(payload), dist)))
+            .collect();
+    }
+
+    /** Returns the `k` units closest to `point`, nearest first, each paired with its distance from `point`. */
+    fn k_closest_to<'man, 'temp>(&'man self, point: &'temp na::Vector5<f32>, k: usize, tree: &'man FehKDTree) -> Vec<(&'man FehUnit, f32)> {
+        return tree.units_with_dist(tree.k_nearest(&(*point).into(), k, &self.m_metric));
+    }
+
+    /** Returns every unit whose stats lie within a distance of `radius` from the unit named `unit_name`,
+     closest first. The unit itself is part of the result at distance 0. */
+    pub fn units_within<'man>(&'man self, unit_name: &str, radius: f32, tree: &'man FehKDTree) -> FehVecPlus<'man> {
+        let center: &FehUnit = self.find_unit(unit_name, tree).unwrap();
+        return FehVecPlus(tree.units_with_dist(tree.within_radius(&center.get_coords(), radius, &self.m_metric)));
     }
 
-    fn vec5_squared_metric_distance(f1: &FehUnit, f2: &FehUnit) -> f32 {
-        let mut squared_dist: f32 = 0f32;
-        for i in 0..5 {
-            squared_dist += (f1.m_stats[i] - f2.m_stats[i]).powf(2f32);
+    /** Returns every unit whose stats fall inside `bounds`, a box over the five stat axes (HP, Atk, Spd, Def, Res)
+     in that order. Units are ordered by name. */
+    pub fn units_in_range<'man>(&'man self, bounds: &AxisBox, tree: &'man FehKDTree) -> FehVec<'man> {
+        let mut in_range: Vec<&FehUnit> = tree.range_search(bounds).into_iter().map(|payload| tree.unit(payload)).collect();
+        in_range.sort_by(|u1, u2| u1.get_name().cmp(u2.get_name()));
+        return FehVec(in_range);
+    }
+
+    /** Answers "who is the most opposite unit to this one": returns the unit whose stats lie farthest from those of the unit
+     named `unit_name`, along with its distance, only considering units of the same weapon type when `same_weapon` is set.
+     Empty if no other unit qualifies. */
+    pub fn most_opposite_to<'man>(&'man self, unit_name: &str, same_weapon: bool, tree: &'man FehKDTree) -> FehVecPlus<'man> {
+        let unit: &FehUnit = self.find_unit(unit_name, tree).unwrap();
+        let accepts = |payload: usize| tree.unit(payload).get_name() != unit_name && (!same_weapon || tree.unit(payload).get_weapon() == unit.get_weapon());
+        return FehVecPlus(tree.units_with_dist(tree.farthest_filtered(&unit.get_coords(), &self.m_metric, accepts).into_iter().collect()));
+    }
+
+    /** Answers "who should join this team": scans the units in increasing distance from the stats of the unit named
+     `unit_name` and returns the first one whose character is not already on the team, along with its distance. The team
+     is that unit together with the units named in `team`, since a team never holds two units of the same character.
+     The scan stops at the first unit that fits, however many alts crowd the neighbourhood. Empty if no unit fits. */
+    pub fn closest_teammate<'man>(&'man self, unit_name: &str, team: &[&str], tree: &'man FehKDTree) -> FehVecPlus<'man> {
+        let unit: &FehUnit = self.find_unit(unit_name, tree).unwrap();
+        let characters: Vec<&str> = team.iter().filter_map(|name| self.find_unit(name, tree)).map(|member| member.get_character())
+            .chain(std::iter::once(unit.get_character())).collect();
+
+        let found = tree.nearest_accepted(&unit.get_coords(), &self.m_metric, |payload| !characters.contains(&tree.unit(payload).get_character()));
+        return FehVecPlus(tree.units_with_dist(found.into_iter().collect()));
+    }
+
+    /** Interpolates between the stats of `unit1` and `unit2` in `steps` steps and returns the unit closest to each step.
+     Every step is searched independently, so the searches are spread over the available cores. */
+    pub fn lerp_units<'man>(&'man self, unit1: &str, unit2: &str, steps: usize, tree: &'man FehKDTree) -> FehVec<'man> {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
+
+        let lerp_points: Vec<na::Vector5<f32>> = MonomialLerp::quick_iter(1f32, start_unit.get_stats(), end_unit.get_stats(), steps).collect();
+        let threads: usize = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        let lerp_units: Vec<&FehUnit> = self.closest_to_batch(&lerp_points, threads, tree).into_iter()
+            .flatten().map(|(unit, _)| unit).collect();
+
+        return FehVec(lerp_units);
+    }
+
+    /** The stats at step `step` of `LERP_STEPS` along the line from `start` to `end`. Each step is computed in one go
+     rather than by accumulating a timestep, so every interpolation over the steps lands on the same points. */
+    fn lerp_step(start: &FehUnit, end: &FehUnit, step: usize) -> na::Vector5<f32> {
+        return (start.get_stats() * (LERP_STEPS - step) as f32 + end.get_stats() * step as f32) / LERP_STEPS as f32;
+    }
+
+    /** Interpolates between the stats of `start` and `end` in `LERP_STEPS` steps, `end` itself included, and finds the
+     units `filter` accepts that are closest to each step. Steps are searched in the stat tree and the added units, where
+     distances are exact, so every unit at exactly the closest distance is returned and rounding can neither split a tie
+     nor make one. Tied units of the list are ordered by name and come before tied added units, which are ordered by when
+     they were added, except that an unfiltered last step puts `end` first. Returns each step's tied units and
+     their distances (empty where no unit is accepted), along with a `QueryTrace` of the step's search. */
+    fn closest_along<'man>(&'man self, filter: &LerpFilter, start: &FehUnit, end: &FehUnit, tree: &'man FehKDTree) -> Vec<(Vec<(&'man FehUnit, f32)>, QueryTrace)> {
+        let mut found: Vec<(Vec<(&FehUnit, f32)>, QueryTrace)> = Vec::with_capacity(LERP_STEPS + 1);
+        for step in 0..=LERP_STEPS {
+            let point: [i16; 5] = FehStatPoint::scale(&Self::lerp_step(start, end, step));
+            let (mut ties, trace) = tree.stat_ties_filtered(&point, |payload| filter.accepts(tree.unit(payload), start, end));
+            ties.sort_by_key(|(payload, _)| *payload);
+            found.push((ties.into_iter().map(|(payload, dist)| (tree.unit(payload), (dist as f32).sqrt() / LERP_STEPS as f32)).collect(), trace));
+        }
+
+        if filter.is_empty() { found.last_mut().unwrap().0.sort_by_key(|(unit, _)| unit.get_name() != end.get_name()); }
+        return found;
+    }
+
+    /** Samples `steps` points along the line from `unit1` to `unit2` and finds the closest unit to each twice, once with
+     every search starting cold from the root and once warm started from the previous step. Reports the total nodes
+     visited by each as a JSON object, to show what warm starting saves on dense sweeps. */
+    pub fn sweep_visits(&self, unit1: &str, unit2: &str, steps: usize, tree: &FehKDTree) -> String {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
+        let (mut cold_visited, mut warm_visited, mut hint): (usize, usize, Option<NearestHint>) = (0, 0, None);
+
+        for point in MonomialLerp::quick_iter(1f32, start_unit.get_stats(), end_unit.get_stats(), steps) {
+            let point: [f32; 5] = point.into();
+            cold_visited += tree.m_tree.nearest_ties_filtered_warm(&point, 0f32, &self.m_metric, None, |_| true).first().map_or(0, |tie| tie.visited());
+            let ties = tree.m_tree.nearest_ties_filtered_warm(&point, 0f32, &self.m_metric, hint, |_| true);
+            warm_visited += ties.first().map_or(0, |tie| tie.visited());
+            hint = ties.first().map(|tie| tie.hint()).or(hint);
         }
 
-        return squared_dist;
+        return format!("{{\"steps\":{},\"cold_visited\":{},\"warm_visited\":{}}}", steps, cold_visited, warm_visited);
     }
 
-    fn closest_to<'man, 'temp>(&'man self, point: &'temp na::Vector5<f32>, tree: &'man FehKDTree) -> &FehUnit {
-        return tree.0.nearest_neighbor(&FehManager::mock_unit(point), |f1,f2| FehManager::vec5_squared_metric_distance(f1, f2)).unwrap();
+    /** Interpolates between the stats of `unit1` and `unit2`, returning the units closest to each step along with their
+     distance. Every unit exactly tied for closest is listed, ordered by name, except that the last step always leads with
+     `unit2` itself when nothing is filtered out. Steps only land on units `filter` accepts; steps where no unit is
+     accepted are left out. */
+    pub fn lerp_units_with_dist<'man>(&'man self, unit1: &str, unit2: &str, filter: &LerpFilter, tree: &'man FehKDTree) -> FehVecTied<'man> {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
+
+        let mut lerped_steps: Vec<Vec<(&FehUnit, f32)>> = self.closest_along(filter, start_unit, end_unit, tree).into_iter().map(|(ties, _)| ties).collect();
+        lerped_steps.retain(|ties| ties.len() > 0);
+        return FehVecTied(lerped_steps);
     }
 
-    pub fn lerp_units<'man>(&'man self, unit1: &str, unit2: &str, tree: &'man FehKDTree) -> FehVec {
-        let start_unit: &FehUnit = self.m_unit_map.get(unit1).unwrap();
-        let end_unit: &FehUnit = self.m_unit_map.get(unit2).unwrap();
+    /** Like `lerp_units_with_dist`, but every interpolation step reports its `k` closest units (the nearest
+     followed by its runner-ups) instead of only the single nearest one. */
+    pub fn lerp_units_with_runners_up<'man>(&'man self, unit1: &str, unit2: &str, k: usize, tree: &'man FehKDTree) -> FehVecRunnersUp<'man> {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
 
-        let mut lerp_units: Vec<&FehUnit> = Vec::new();
-        for vec in MonomialLerp::quick_iter(1f32, start_unit.get_stats(), end_unit.get_stats(), 10) {
-            lerp_units.push(self.closest_to(&vec, tree));
+        let mut lerped_steps: Vec<FehVecPlus> = Vec::new();
+        for lerp_point in MonomialLerp::quick_iter(1f32, start_unit.get_stats(), end_unit.get_stats(), LERP_STEPS) {
+            lerped_steps.push(FehVecPlus(self.k_closest_to(&lerp_point, k, tree)));
         }
-        
-        return FehVec(lerp_units);
+
+        lerped_steps.push(FehVecPlus(self.k_closest_to(end_unit.get_stats(), k, tree)));
+        return FehVecRunnersUp(lerped_steps);
     }
 
-    pub fn lerp_units_with_dist<'man>(&'man self, unit1: &str, unit2: &str, tree: &'man FehKDTree) -> FehVecPlus {
-        let start_unit: &FehUnit = self.m_unit_map.get(unit1).unwrap();
-        let end_unit: &FehUnit = self.m_unit_map.get(unit2).unwrap();
+    /** Answers "who lies between these two units" exactly: returns the `k` units whose stats lie closest to the straight
+     line segment between the stats of `unit1` and `unit2`, the two units themselves left out. Each unit comes with its
+     distance to the segment and with t, how far along the segment (0 at `unit1`, 1 at `unit2`) it comes closest to the
+     unit. Units are ordered by t, so they read as a path from `unit1` to `unit2`. */
+    pub fn units_between<'man>(&'man self, unit1: &str, unit2: &str, k: usize, tree: &'man FehKDTree) -> FehVecSegment<'man> {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
+
+        let is_endpoint = |payload: usize| tree.unit(payload).get_name() == unit1 || tree.unit(payload).get_name() == unit2;
+        let mut between: Vec<(&FehUnit, f32, f32)> = tree.nearest_to_segment_filtered(&start_unit.get_coords(), &end_unit.get_coords(), k, |payload| !is_endpoint(payload))
+            .into_iter().map(|(payload, dist, t)| (tree.unit(payload), dist, t)).collect();
 
-        let mut lerped_units : Vec<(&FehUnit, f32)> = Vec::new();
-        for lerp_point in MonomialLerp::quick_iter(1f32, start_unit.get_stats(), end_unit.get_stats(), 10) {
-            let nearest: &FehUnit = self.closest_to(&lerp_point, tree);
-            let distance: f32 = nearest.get_stats().metric_distance(&lerp_point);
-            lerped_units.push((nearest, distance));
+        between.sort_by(|u1, u2| u1.2.total_cmp(&u2.2));
+        return FehVecSegment(between);
+    }
+
+    /** Reports the shape of `tree` (depths, splits per stat and balance) as a JSON object. */
+    pub fn tree_stats(&self, tree: &FehKDTree) -> String {
+        return tree.m_tree.stats().to_json(&STAT_NAMES);
+    }
+
+    /** Exports the partition of `tree` so it can be drawn: as a Graphviz DOT digraph if `format` is "dot", otherwise as
+     nested JSON. Every node is labelled with the name of its unit and its split stat and value. */
+    pub fn tree_export(&self, format: &str, tree: &FehKDTree) -> String {
+        let label = |payload: usize| tree.unit(payload).get_name().to_owned();
+        return match format {
+            "dot" => tree.m_tree.export_dot(&STAT_NAMES, label),
+            _ => tree.m_tree.export_json(&STAT_NAMES, label)
+        };
+    }
+
+    /** Searches `tree` for the unit closest to `stats` and reports the answer together with the ordered log of every step
+     the search took, as a JSON object {"unit":..,"distance":..,"trace":{..},"events":[..]}, so the search can be replayed.
+     Events name tree nodes by index and label each with the name of its unit. */
+    pub fn nearest_search_log(&self, stats: &[f32; 5], tree: &FehKDTree) -> String {
+        let (found, trace) = tree.m_tree.nearest_neighbor_logged(stats, &self.m_metric);
+        let Some((payload, dist)) = found else { return String::from("{}"); };
+        let label = |node: usize| tree.unit(tree.m_tree.payload_of(node)).get_name().to_owned();
+        let events: Vec<String> = trace.events().iter().map(|event| event.to_json(label)).collect();
+        return format!("{{\"unit\":{},\"distance\":{},\"trace\":{},\"events\":[{}]}}", json_string(tree.unit(payload).get_name()), dist, trace.to_json(), events.join(","));
+    }
+
+    /** Reports the backend of `tree` and every disagreement its self-check has logged so far as a JSON object. */
+    pub fn self_check_report(&self, tree: &FehKDTree) -> String {
+        let disagreements: Vec<String> = tree.disagreements().iter().map(|msg| json_string(msg)).collect();
+        return format!("{{\"backend\":\"{:?}\",\"disagreements\":[{}]}}", tree.backend(), disagreements.join(","));
+    }
+
+    /** Interpolates between the stats of `unit1` and `unit2` like an unfiltered `lerp_units_with_dist`, searching the same
+     stat tree, and reports the first of each step's tied units along with how much of the stat tree its search had to
+     look at. */
+    pub fn lerp_units_traced<'man>(&'man self, unit1: &str, unit2: &str, tree: &'man FehKDTree) -> FehVecTraced<'man> {
+        let start_unit: &FehUnit = self.find_unit(unit1, tree).unwrap();
+        let end_unit: &FehUnit = self.find_unit(unit2, tree).unwrap();
+
+        let mut lerped_steps: Vec<(&FehUnit, f32, QueryTrace)> = Vec::new();
+        for (ties, trace) in self.closest_along(&LerpFilter::new(), start_unit, end_unit, tree) {
+            let (unit, dist) = ties[0];
+            lerped_steps.push((unit, dist, trace));
         }
 
-        lerped_units.push((self.get_unit(unit2), 0f32));
-        return FehVecPlus(lerped_units);
+        return FehVecTraced(lerped_steps);
     }
 }
 
@@ -221,12 +956,24 @@ pub struct FehVec<'man>(Vec<&'man FehUnit>);
 #[derive(Debug)]
 pub struct FehVecPlus<'man>(Vec<(&'man FehUnit, f32)>);
 
+#[derive(Debug)]
+pub struct FehVecRunnersUp<'man>(Vec<FehVecPlus<'man>>);
+
+#[derive(Debug)]
+pub struct FehVecSegment<'man>(Vec<(&'man FehUnit, f32, f32)>);
+
+#[derive(Debug)]
+pub struct FehVecTraced<'man>(Vec<(&'man FehUnit, f32, QueryTrace)>);
+
+#[derive(Debug)]
+pub struct FehVecTied<'man>(Vec<Vec<(&'man FehUnit, f32)>>);
+
 impl<'man> FehVec<'man> {
     fn json_names<I: Iterator<Item = &'man FehUnit>>(mut iter: I) -> String {
         let mut json_list: String = String::from("[");
-        json_list = json_list + "\"" + iter.next().unwrap().get_name() + "\"";
+        json_list += &json_string(iter.next().unwrap().get_name());
         for unit in iter {
-            json_list = json_list + ",\"" + unit.get_name() + "\"";
+            json_list = json_list + "," + &json_string(unit.get_name());
         }
 
         return json_list + "]";
@@ -235,10 +982,11 @@ impl<'man> FehVec<'man> {
     pub fn to_json_names(self) -> String {
         let mut iter = self.0.into_iter();
         let mut json_list: String = String::from("[");
-        json_list = json_list + "\"" + iter.next().unwrap().get_name() + "\"";
+        if iter.len() == 0 { return json_list + "]"; }
+        json_list += &json_string(iter.next().unwrap().get_name());
 
         for unit in iter {
-            json_list = json_list + ",\"" + unit.get_name() + "\"";
+            json_list = json_list + "," + &json_string(unit.get_name());
         }
 
         return json_list + "]";
@@ -248,9 +996,8 @@ impl<'man> FehVec<'man> {
 impl<'man> FehVecPlus<'man> {
     fn format_unit_distance_tuple(unit_and_distance: (&FehUnit, f32)) -> String {
         let mut stringified = String::from("[");
-        stringified += "\"";
-        stringified += unit_and_distance.0.get_name();
-        stringified += "\", ";
+        stringified += &json_string(unit_and_distance.0.get_name());
+        stringified += ", ";
         stringified += unit_and_distance.1.to_string().as_str();
         return stringified + "]";
     }
@@ -258,6 +1005,7 @@ impl<'man> FehVecPlus<'man> {
     pub fn to_json_names(self) -> String {
         let mut iter = self.0.into_iter();
         let mut json_list: String = String::from("[");
+        if iter.len() == 0 { return json_list + "]"; }
         
         json_list += &Self::format_unit_distance_tuple(iter.next().unwrap());
         for unit_dist_pair in iter {
@@ -269,9 +1017,344 @@ impl<'man> FehVecPlus<'man> {
     }
 }
 
+impl<'man> FehVecRunnersUp<'man> {
+    pub fn to_json_names(self) -> String {
+        let mut iter = self.0.into_iter();
+        let mut json_list: String = String::from("[");
+
+        json_list += &iter.next().unwrap().to_json_names();
+        for step in iter {
+            json_list += ",";
+            json_list += &step.to_json_names();
+        }
+
+        return json_list + "]";
+    }
+}
+
+impl<'man> FehVecSegment<'man> {
+    pub fn to_json_names(self) -> String {
+        let units: Vec<String> = self.0.into_iter().map(|(unit, dist, t)| format!("[{}, {}, {}]", json_string(unit.get_name()), dist, t)).collect();
+        return String::from("[") + &units.join(",") + "]";
+    }
+}
+
+impl<'man> FehVecTied<'man> {
+    /** Lists each step as [name, distance, [tied names]]: the first of its tied units with their shared distance, followed
+     by every tied unit (the first one included). */
+    pub fn to_json_names(self) -> String {
+        let steps: Vec<String> = self.0.into_iter().map(|ties| {
+            let names: Vec<String> = ties.iter().map(|(unit, _)| json_string(unit.get_name())).collect();
+            format!("[{}, {}, [{}]]", json_string(ties[0].0.get_name()), ties[0].1, names.join(","))
+        }).collect();
+
+        return String::from("[") + &steps.join(",") + "]";
+    }
+}
+
+impl<'man> FehVecTraced<'man> {
+    pub fn to_json_names(self) -> String {
+        let steps: Vec<String> = self.0.into_iter()
+            .map(|(unit, dist, trace)| format!("{{\"unit\":{},\"distance\":{},\"trace\":{}}}", json_string(unit.get_name()), dist, trace.to_json()))
+            .collect();
+
+        return String::from("[") + &steps.join(",") + "]";
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{CKDTree, FehUnit};
+    use super::{FehBackend, FehUnit, FehManager, FehKDTree, FehVecSegment, FehVpMetric, LerpFilter, MAX_ADDED_UNITS, STAT_NAMES};
+    use crate::kdtree::AxisBox;
+    use crate::kdtree::CKDTree;
+    use crate::metric::Euclidean;
+    use std::collections::BTreeMap;
+
+  // A small roster where the stats of every unit lie on the line between "Start" and "End"
+  fn mock_manager() -> FehManager {
+    let units: [(&str, &str, &str, f32); 6] = [
+        ("Start", "Alm", "Sword", 0f32), ("Start Alt", "Alm", "Bow", 1f32), ("Middle Sword", "Celica", "Sword", 5f32),
+        ("Middle Tome", "Mae", "Tome", 5.5f32), ("End Alt", "Boey", "Tome", 9f32), ("End", "Boey", "Sword", 10f32)
+    ];
+
+    let mut unit_map = BTreeMap::new();
+    for (name, character, weapon, at) in units {
+        let stats = na::Vector5::new(40f32, 30f32, 30f32, 20f32, 20f32) + na::Vector5::repeat(at);
+        unit_map.insert(name.to_owned(), FehUnit::new(name.to_owned(), character.to_owned(), stats).with_weapon(weapon.to_owned()).as_arc());
+    }
+
+    return FehManager { m_unit_map: unit_map, m_metric: Euclidean };
+  }
+
+  #[test]
+  fn snapshot_test() {
+    let dir = std::env::temp_dir().join(format!("feh_snapshot_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (csv_path, snapshot_path) = (dir.join("units.csv"), dir.join("units.snapshot"));
+    let (csv_path, snapshot_path) = (csv_path.to_str().unwrap(), snapshot_path.to_str().unwrap());
+
+    // one header row, then rows of 36 columns: name in column 1, character in 2, weapon in 5 and stats in 29 to 33
+    let mut csv: String = String::from("header\n");
+    for (i, (name, character)) in [("Ike", "Ike"), ("Roy", "Roy"), ("Brave Ike", "Ike"), ("Camus", "Camus")].iter().enumerate() {
+        let mut columns: Vec<String> = vec!["x".to_owned(); 36];
+        (columns[1], columns[2], columns[5]) = (name.to_string(), character.to_string(), "Sword".to_owned());
+        for stat in 0..5 { columns[29 + stat] = (30 + 3 * i + stat).to_string(); }
+        csv += &(columns.join(",") + "\n");
+    }
+
+    std::fs::write(csv_path, &csv).unwrap();
+    let _ = std::fs::remove_file(snapshot_path);
+
+    // no snapshot yet: parses the CSV, then writes one
+    let (parsed, parsed_tree) = FehManager::init_with_snapshot(csv_path, snapshot_path).unwrap();
+    assert_eq!(parsed.num_units(), 4);
+    let source_hash: u64 = crate::snapshot::fnv1a_64(csv.as_bytes());
+    let (loaded, loaded_tree) = FehManager::load_snapshot(snapshot_path, source_hash).unwrap();
+
+    assert_eq!(loaded.all_units(), parsed.all_units());
+    assert_eq!(loaded.get_unit("Brave Ike").get_weapon(), "Sword");
+    assert_eq!(loaded.get_unit("Brave Ike").get_stats(), parsed.get_unit("Brave Ike").get_stats());
+    assert!(loaded_tree.m_tree.raw_nodes().zip(parsed_tree.m_tree.raw_nodes())
+        .all(|(n1, n2)| (n1.0, n1.1, n1.3, loaded_tree.unit(n1.4).get_name()) == (n2.0, n2.1, n2.3, parsed_tree.unit(n2.4).get_name())));
+
+    // changing the CSV makes the snapshot stale
+    std::fs::write(csv_path, csv.replace("Camus", "Sable Camus")).unwrap();
+    assert_eq!(FehManager::load_snapshot(snapshot_path, crate::snapshot::fnv1a_64(csv.replace("Camus", "Sable Camus").as_bytes())).err(), Some("snapshot is stale"));
+    let (reparsed, _) = FehManager::init_with_snapshot(csv_path, snapshot_path).unwrap();
+    assert!(reparsed.contains("Sable Camus"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn closest_to_batch_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let points: Vec<na::Vector5<f32>> = (0..40).map(|i| na::Vector5::new(40f32, 30f32, 30f32, 20f32, 20f32) + na::Vector5::repeat(i as f32 * 0.25)).collect();
+
+    let batch: Vec<Option<(&FehUnit, f32)>> = man.closest_to_batch(&points, 4, &tree);
+    assert_eq!(batch.len(), points.len());
+    for (point, found) in points.iter().zip(batch.iter()) {
+        let (found, expected) = (found.unwrap(), man.closest_to(point, &tree));
+        assert_eq!((found.0.get_name(), found.1), (expected.0.get_name(), expected.1));
+    }
+  }
+
+  #[test]
+  fn backends_test() {
+    let man: FehManager = mock_manager();
+    assert_eq!(FehKDTree::construct_kdtree(&man).backend(), FehBackend::LINEAR_SCAN);
+    assert_eq!(FehBackend::for_size(FehBackend::LINEAR_SCAN_LIMIT), FehBackend::KD_TREE);
+    assert_eq!(FehBackend::from_name("self_check"), Some(FehBackend::SELF_CHECK));
+
+    let bounds: AxisBox = AxisBox::new(5).at_least(0, 44f32).at_most(1, 39f32);
+    let answers = |tree: &FehKDTree| {
+      let within: Vec<f32> = man.units_within("Middle Tome", 4f32, tree).0.iter().map(|(_, dist)| *dist).collect();
+      let nearest: Vec<f32> = man.k_closest_to(&na::Vector5::new(43f32, 33f32, 34f32, 23f32, 22f32), 3, tree).iter().map(|(_, dist)| *dist).collect();
+      let in_range: Vec<String> = man.units_in_range(&bounds, tree).0.iter().map(|unit| unit.get_name().to_owned()).collect();
+      (within, nearest, in_range)
+    };
+
+    let checked: FehKDTree = FehKDTree::construct_kdtree(&man).with_backend(FehBackend::SELF_CHECK);
+    let expected = answers(&FehKDTree::construct_kdtree(&man).with_backend(FehBackend::KD_TREE));
+    assert_eq!(answers(&FehKDTree::construct_kdtree(&man)), expected);
+    assert_eq!(answers(&checked), expected);
+    assert_eq!(answers(&FehKDTree::construct_kdtree(&man).with_backend(FehBackend::VP_TREE)), expected);
+    assert_eq!(expected.2, vec!["End Alt", "Middle Sword", "Middle Tome"]);
+    assert!(checked.disagreements().is_empty());
+    assert_eq!(man.self_check_report(&checked), "{\"backend\":\"SELF_CHECK\",\"disagreements\":[]}");
+
+    // by angle, twice the stats of "Start" lie on top of it, though by distance "End" is closest
+    let doubled: na::Vector5<f32> = man.get_unit("Start").get_stats() * 2f32;
+    let angular: FehKDTree = FehKDTree::construct_kdtree(&man).with_backend(FehBackend::VP_TREE).with_vp_metric(FehVpMetric::ANGULAR);
+    let (closest, dist) = man.closest_to(&doubled, &angular);
+    assert_eq!(closest.get_name(), "Start");
+    assert!(dist < 1e-3);
+    assert_eq!(man.closest_to(&doubled, &FehKDTree::construct_kdtree(&man)).0.get_name(), "End");
+    assert_eq!(FehVpMetric::from_name("angular"), Some(FehVpMetric::ANGULAR));
+  }
+
+  #[test]
+  fn add_unit_test() {
+    let man: FehManager = mock_manager();
+    let base = na::Vector5::new(40f32, 30f32, 30f32, 20f32, 20f32);
+    let names = |found: Vec<(&FehUnit, f32)>| found.iter().map(|(unit, _)| unit.get_name().to_owned()).collect::<Vec<String>>();
+
+    for backend in [FehBackend::KD_TREE, FehBackend::LINEAR_SCAN, FehBackend::SELF_CHECK] {
+      let tree: FehKDTree = FehKDTree::construct_kdtree(&man).with_backend(backend);
+      for (name, character, at) in [("Custom Mid", "Custom", 7f32), ("Custom Twin", "Twin", 5.4f32), ("Custom \"Far\"", "Custom", 30f32)] {
+        assert_eq!(man.add_unit(name, character, "Lance", (base + na::Vector5::repeat(at)).into(), &tree), Ok(()));
+      }
+
+      assert_eq!(tree.added_count(), 3);
+      assert_eq!(man.add_unit("Custom Mid", "Custom", "Lance", [0f32; 5], &tree), Err("a unit with that name already exists"));
+      assert_eq!(man.add_unit("Start", "Alm", "Sword", [0f32; 5], &tree), Err("a unit with that name already exists"));
+      assert_eq!(man.add_unit("Broken", "Custom", "Lance", [f32::NAN; 5], &tree), Err("unit stats must be finite numbers"));
+
+      // added units are found by every query, and can be named as the unit a query is about
+      assert!(man.contains_unit("Custom Mid", &tree) && !man.contains("Custom Mid"));
+      assert_eq!(man.closest_to(&(base + na::Vector5::repeat(7.2f32)), &tree).0.get_name(), "Custom Mid");
+      assert_eq!(names(man.k_closest_to(&(base + na::Vector5::repeat(7f32)), 2, &tree)), vec!["Custom Mid", "Middle Tome"]);
+      assert_eq!(names(man.units_within("Custom Mid", 2f32, &tree).0), vec!["Custom Mid"]);
+      assert_eq!(man.units_in_range(&AxisBox::new(5).between(0, 46.5, 47.5), &tree).0.iter().map(|unit| unit.get_name()).collect::<Vec<&str>>(), vec!["Custom Mid"]);
+      assert_eq!(names(man.most_opposite_to("Start", false, &tree).0), vec!["Custom \"Far\""]);
+      assert_eq!(names(man.closest_teammate("Middle Tome", &[], &tree).0), vec!["Custom Twin"]);
+      assert!(man.units_between("Start", "End", 6, &tree).0.iter().any(|(unit, _, _)| unit.get_name() == "Custom Mid"));
+
+      let lerped = man.lerp_units_with_dist("Start", "End", &LerpFilter::new(), &tree);
+      assert_eq!((lerped.0[7][0].0.get_name(), lerped.0[7][0].1), ("Custom Mid", 0f32));
+      assert_eq!(man.lerp_units_with_dist("Start", "Custom Mid", &LerpFilter::new(), &tree).0.last().unwrap()[0].0.get_name(), "Custom Mid");
+      assert_eq!(man.lerp_units("Start", "End", 10, &tree).0[7].get_name(), "Custom Mid");
+      assert!(man.units_within("Custom \"Far\"", 0f32, &tree).to_json_names().starts_with("[[\"Custom \\\"Far\\\"\", 0"));
+    }
+
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    for i in 0..MAX_ADDED_UNITS { assert_eq!(man.add_unit(&format!("Custom {}", i), "Custom", "Lance", [i as f32; 5], &tree), Ok(())); }
+    assert_eq!(man.add_unit("One Too Many", "Custom", "Lance", [0f32; 5], &tree), Err("no more units can be added"));
+    assert_eq!(man.num_units(), 6);
+  }
+
+  #[test]
+  fn most_opposite_to_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let opposite = |unit: &str, same_weapon: bool| man.most_opposite_to(unit, same_weapon, &tree).0.iter().map(|(u, _)| u.get_name().to_owned()).collect::<Vec<String>>();
+
+    assert_eq!(opposite("Start", false), vec!["End"]);
+    assert_eq!(opposite("Middle Tome", false), vec!["Start"]);
+    assert_eq!(opposite("Middle Tome", true), vec!["End Alt"]);
+    assert_eq!(opposite("End", true), vec!["Start"]);
+    assert!(opposite("Start Alt", true).is_empty());
+    assert_eq!(man.most_opposite_to("Start", false, &tree).to_json_names(), format!("[[\"End\", {}]]", 500f32.sqrt()));
+  }
+
+  #[test]
+  fn closest_teammate_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let teammate = |unit: &str, team: &[&str]| man.closest_teammate(unit, team, &tree).0.iter().map(|(u, _)| u.get_name().to_owned()).collect::<Vec<String>>();
+
+    // "Start Alt" is closer, but it is the same character as "Start"
+    assert_eq!(teammate("Start", &[]), vec!["Middle Sword"]);
+    assert_eq!(teammate("Start", &["Middle Sword"]), vec!["Middle Tome"]);
+    assert_eq!(teammate("End", &["End Alt", "Unknown"]), vec!["Middle Tome"]);
+    assert!(teammate("Start", &["Middle Sword", "Middle Tome", "End"]).is_empty());
+    assert_eq!(man.closest_teammate("Start", &[], &tree).to_json_names(), format!("[[\"Middle Sword\", {}]]", 125f32.sqrt()));
+  }
+
+  #[test]
+  fn lerp_filter_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let names = |filter: LerpFilter| man.lerp_units_with_dist("Start", "End", &filter, &tree).0.iter().map(|ties| ties[0].0.get_name().to_owned()).collect::<Vec<String>>();
+
+    let unfiltered: Vec<String> = names(LerpFilter::new());
+    assert_eq!(unfiltered.first().unwrap(), "Start");
+    assert_eq!(unfiltered.last().unwrap(), "End");
+
+    let no_endpoints: Vec<String> = names(LerpFilter::new().exclude_endpoints(true));
+    assert!(no_endpoints.iter().all(|n| n != "Start" && n != "End"));
+    assert_eq!(no_endpoints.first().unwrap(), "Start Alt");
+
+    let no_same_character: Vec<String> = names(LerpFilter::new().exclude_same_character(true));
+    assert!(no_same_character.iter().all(|n| n == "Middle Sword" || n == "Middle Tome"));
+
+    let tomes: Vec<String> = names(LerpFilter::new().only_weapon(Some("Tome".to_owned())));
+    assert!(tomes.iter().all(|n| n == "Middle Tome" || n == "End Alt"));
+  }
+
+  #[test]
+  fn units_between_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+
+    let between = man.units_between("Start", "End", 4, &tree);
+    let names: Vec<&str> = between.0.iter().map(|(unit, _, _)| unit.get_name()).collect();
+    assert_eq!(names, vec!["Start Alt", "Middle Sword", "Middle Tome", "End Alt"]);
+    assert!(between.0.iter().all(|(_, dist, _)| *dist < 1e-3));
+    assert!((between.0[1].2 - 0.5).abs() < 1e-5);
+
+    assert_eq!(man.units_between("Start", "End", 1, &tree).to_json_names().matches("\"").count(), 2);
+
+    let quoted: FehUnit = FehUnit::new("Líf \"Dead Lord\"".to_owned(), "Líf".to_owned(), na::Vector5::zeros());
+    assert_eq!(FehVecSegment(vec![(&quoted, 0.5, 0.25)]).to_json_names(), "[[\"Líf \\\"Dead Lord\\\"\", 0.5, 0.25]]");
+  }
+
+  #[test]
+  fn lerp_ties_test() {
+    let mut man: FehManager = mock_manager();
+    for (twin, of) in [("Middle Axe", "Middle Sword"), ("Another End", "End")] {
+      let unit: &FehUnit = man.get_unit(of);
+      let twin_unit = FehUnit::new(twin.to_owned(), "Twin".to_owned(), *unit.get_stats()).with_weapon("Axe".to_owned()).as_arc();
+      man.m_unit_map.insert(twin.to_owned(), twin_unit);
+    }
+
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let steps = man.lerp_units_with_dist("Start", "End", &LerpFilter::new(), &tree);
+    let tied_names = |ties: &Vec<(&FehUnit, f32)>| ties.iter().map(|(unit, _)| unit.get_name().to_owned()).collect::<Vec<String>>();
+
+    assert_eq!(tied_names(&steps.0[5]), vec!["Middle Axe", "Middle Sword"]);
+    assert_eq!(tied_names(steps.0.last().unwrap()), vec!["End", "Another End"]);
+    assert!(steps.0.iter().all(|ties| ties.iter().all(|(_, dist)| *dist == ties[0].1)));
+
+    let json: String = steps.to_json_names();
+    assert!(json.starts_with("[[\"Start\", 0, [\"Start\"]],"));
+    assert!(json.contains("[\"Middle Axe\", 0, [\"Middle Axe\",\"Middle Sword\"]]"));
+    assert!(json.ends_with("[\"End\", 0, [\"End\",\"Another End\"]]]"));
+  }
+
+  #[test]
+  fn sweep_visits_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    let report: String = man.sweep_visits("Start", "End", 200, &tree);
+
+    let count = |key: &str| report.split(&format!("\"{}\":", key)).nth(1).unwrap().split(|c| c == ',' || c == '}').next().unwrap().parse::<usize>().unwrap();
+    assert_eq!(count("steps"), 200);
+    assert!(count("warm_visited") <= count("cold_visited"));
+
+    let lerp_names: Vec<&str> = man.lerp_units("Start", "End", 10, &tree).0.iter().map(|unit| unit.get_name()).collect();
+    assert_eq!(lerp_names.first(), Some(&"Start"));
+    assert_eq!(lerp_names.len(), 10);
+  }
+
+  #[test]
+  fn diagnostics_test() {
+    let man: FehManager = mock_manager();
+    let tree: FehKDTree = FehKDTree::construct_kdtree(&man);
+    assert!(man.tree_stats(&tree).starts_with("{\"nodes\":6,\"removed\":0,"));
+    assert!(man.tree_stats(&tree).contains("\"axis_splits\":{\"HP\":"));
+
+    let (dot, json) = (man.tree_export("dot", &tree), man.tree_export("json", &tree));
+    assert_eq!(dot.matches(" -> ").count(), 5);
+    assert!(json.starts_with("{\"label\":\"") && json.matches("\"label\":").count() == 6);
+    assert!(STAT_NAMES.iter().any(|stat| json.contains(&format!("\"axis\":\"{}\"", stat))));
+
+    let log: String = man.nearest_search_log(man.m_unit_map.get("End").unwrap().get_stats().as_ref(), &tree);
+    assert!(log.starts_with("{\"unit\":\"End\",\"distance\":0,\"trace\":{\"visited\":"));
+    assert!(log.contains("\"events\":[{\"event\":\"DESCEND\",\"node\":0,\"node_label\":\""));
+    assert!(log.contains("{\"event\":\"CANDIDATE\",\"node\":") && log.ends_with("}]}"));
+
+    let traced = man.lerp_units_traced("Start", "End", &tree);
+    let untraced = man.lerp_units_with_dist("Start", "End", &LerpFilter::new(), &tree);
+    assert_eq!(traced.0.len(), untraced.0.len());
+    for ((unit, dist, trace), ties) in traced.0.iter().zip(untraced.0.iter()) {
+        assert_eq!((unit.get_name(), *dist), (ties[0].0.get_name(), ties[0].1));
+        assert!(trace.visited() > 0 && trace.visited() <= 6);
+    }
+
+    assert!(traced.to_json_names().starts_with("[{\"unit\":\"Start\",\"distance\":0,\"trace\":{\"visited\":"));
+
+    // unit names are escaped wherever they are written as JSON strings
+    let mut quoted: FehManager = mock_manager();
+    let name: &str = "Líf \"Dead Lord\"";
+    quoted.m_unit_map.insert(name.to_owned(), FehUnit::new(name.to_owned(), "Líf".to_owned(), na::Vector5::new(0f32, 0f32, 0f32, 0f32, 0f32)).as_arc());
+    let quoted_tree: FehKDTree = FehKDTree::construct_kdtree(&quoted);
+    assert!(quoted.tree_export("json", &quoted_tree).contains("\"label\":\"Líf \\\"Dead Lord\\\"\""));
+    assert!(quoted.nearest_search_log(&[0f32; 5], &quoted_tree).starts_with("{\"unit\":\"Líf \\\"Dead Lord\\\"\","));
+    assert!(quoted.lerp_units_with_dist(name, "End", &LerpFilter::new(), &quoted_tree).to_json_names().starts_with("[[\"Líf \\\"Dead Lord\\\"\", 0, [\"Líf \\\"Dead Lord\\\"\"]]"));
+    assert!(quoted.lerp_units_traced(name, "End", &quoted_tree).to_json_names().starts_with("[{\"unit\":\"Líf \\\"Dead Lord\\\"\","));
+  }
 
   #[test]
   fn kdtree_test() {
@@ -280,24 +1363,28 @@ mod tests {
     let my_unit = FehUnit {
         m_name: "Dragonlord Tiki".to_owned(),
         m_character: "Tiki".to_owned(),
+        m_weapon: "Breath".to_owned(),
         m_stats: na::Vector5::new(100f32, 55f32, 65f32, 55f32, 45f32)
     }.as_arc();
 
     let unit_two = FehUnit {
         m_name: "King Hector".to_owned(),
         m_character: "Hector".to_owned(),
+        m_weapon: "Axe".to_owned(),
         m_stats: na::Vector5::new(150f32, 80f32, 5f32, 70f32, 55f32)
     }.as_arc();
 
     let unit_three = FehUnit {
         m_name: "Queen Camilla".to_owned(),
         m_character: "Camilla".to_owned(),
+        m_weapon: "Axe".to_owned(),
         m_stats: na::Vector5::new(85f32, 95f32, 80f32, 40f32, 40f32)
     }.as_arc();
 
     let unit_four = FehUnit {
         m_name: "Goddess Loki".to_owned(),
         m_character: "Loki".to_owned(),
+        m_weapon: "Tome".to_owned(),
         m_stats: na::Vector5::new(70f32, 180f32, 100f32, 20f32, 50f32)
     }.as_arc();
 
@@ -307,14 +1394,15 @@ mod tests {
 
     let mock_unit = FehUnit::new("Summoner".to_owned(), "Summoner".to_owned(), na::Vector5::new(55f32, 75f32, 90f32, 30f32, 80f32));
     let mut feh_tree = CKDTree::new(5);
-    feh_tree.push_wrapped(unit_two);
-    feh_tree.push_wrapped(unit_three);
-    feh_tree.push_wrapped(unit_four);
-    feh_tree.push_wrapped(unit_five);
-    feh_tree.push_wrapped(unit_six);
-    feh_tree.push_wrapped(unit_seven);
-
-    let closest = feh_tree.nearest_neighbor(&mock_unit, |u1,u2| u1.m_stats.metric_distance(&u2.m_stats)).unwrap();
+    feh_tree.push(unit_two);
+    feh_tree.push(unit_three);
+    feh_tree.push(unit_four);
+    feh_tree.push(unit_five);
+    feh_tree.push(unit_six);
+    feh_tree.push(unit_seven);
+
+    let (closest, distance) = feh_tree.nearest_neighbor(&mock_unit, &Euclidean).unwrap();
+    assert_eq!(distance, closest.m_stats.metric_distance(&mock_unit.m_stats));
 
     println!("FEH TREE: {:?}", feh_tree);
     println!("CLOSEST UNIT: {:?}", closest);
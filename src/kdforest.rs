@@ -0,0 +1,276 @@
+use crate::kdtree::{AxisBox, CKDTree, KdPoint, SplitRule};
+use crate::metric::{KdNumber, KdScalar, Metric};
+
+/** A dynamic set of points kept as a logarithmic number of statically balanced `CKDTree`s (the Bentley–Saxe method). Slot
+ `i` is either empty or holds a tree built from median splits out of 2^i inserted points. Inserting a point works like
+ incrementing a binary counter: the point and every full slot below the first empty one are merged into a single new
+ tree, built from scratch into that empty slot. Every tree therefore stays balanced however points arrive, and each
+ point is rebuilt O(log n) times over its life rather than the whole set once per insertion. Queries search every tree
+ and merge their answers. A removal that leaves slot `i` with fewer than 2^(i-1) live points empties the slot and
+ pushes its points back in, so removed points never pile up in the larger slots. */
+#[derive(Debug)]
+pub struct KdForest<P> {
+    m_k: usize,
+    m_slots: Vec<Option<CKDTree<P>>>,
+    m_split_rule: SplitRule
+}
+
+impl<P: KdPoint> KdForest<P> {
+    /** Constructs an empty `KdForest` over `dimensions` axes whose trees are built with the split rule `rule`. */
+    pub fn new(dimensions: usize, rule: SplitRule) -> Self {
+        return KdForest { m_k: dimensions, m_slots: Vec::new(), m_split_rule: rule };
+    }
+
+    pub fn dimensions(&self) -> usize {
+        return self.m_k;
+    }
+
+    pub fn split_rule(&self) -> SplitRule {
+        return self.m_split_rule;
+    }
+
+    /** Returns the number of points in this forest that have not been removed. */
+    pub fn size(&self) -> usize {
+        return self.trees().map(|tree| tree.size()).sum();
+    }
+
+    /** Returns the trees of this forest, from the smallest slot to the largest. */
+    pub fn trees(&self) -> impl Iterator<Item = &CKDTree<P>> {
+        return self.m_slots.iter().flatten();
+    }
+
+    /** Returns every point of this forest that has not been removed, tree by tree. */
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        return self.trees().flat_map(|tree| tree.iter().map(|(_, point)| point));
+    }
+}
+
+impl<P: KdPoint> KdForest<P> where P::Scalar: KdScalar {
+    /** Inserts a point, merging it with the trees of every full slot below the first empty one into a new balanced tree
+     in that slot. A forest of dimension 0 takes its dimension from the first point pushed into it. */
+    pub fn push(&mut self, point: P) -> &mut Self {
+        if self.m_k == 0 { self.m_k = point.dimensions(); }
+        let mut points: Vec<P> = vec![point];
+        let mut slot: usize = 0;
+        while let Some(tree) = self.m_slots.get_mut(slot).and_then(|tree| tree.take()) {
+            points.extend(tree.into_points());
+            slot += 1;
+        }
+
+        if slot == self.m_slots.len() { self.m_slots.push(None); }
+        self.m_slots[slot] = Some(CKDTree::from_median(self.m_k, points, self.m_split_rule));
+        return self;
+    }
+
+    /** Removes a point equal to `point` (along every axis) from whichever tree holds it, as `CKDTree::remove` does. When
+     that leaves the tree's slot less than half full, the slot is emptied and its points are pushed back in. Returns
+     whether a matching point was found. */
+    pub fn remove<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&mut self, point: &Q) -> bool {
+        let slot: usize = match (0..self.m_slots.len()).find(|slot| self.m_slots[*slot].as_mut().is_some_and(|tree| tree.remove(point))) {
+            Some(slot) => slot,
+            None => return false
+        };
+
+        if 2 * self.m_slots[slot].as_ref().unwrap().size() < 1 << slot {
+            let points: Vec<P> = self.m_slots[slot].take().unwrap().into_points();
+            self.extend(points);
+        }
+
+        return true;
+    }
+
+    /** Merges every tree of this forest into a single balanced `CKDTree`, dropping removed points. */
+    pub fn into_tree(self) -> CKDTree<P> {
+        let points: Vec<P> = self.m_slots.into_iter().flatten().flat_map(|tree| tree.into_points()).collect();
+        return CKDTree::from_median(self.m_k, points, self.m_split_rule);
+    }
+
+    /** Find every point of this forest that lies inside the axis-aligned box `bounds`, tree by tree. */
+    pub fn range_search<'t>(&'t self, bounds: &AxisBox<P::Scalar>) -> Vec<&'t P> {
+        return self.trees().flat_map(|tree| tree.range_search(bounds)).collect();
+    }
+
+    /** Find the nearest neighbor to the input point across every tree under the distance `metric`. Returns the nearest
+     point along with its distance from the input point. */
+    pub fn nearest_neighbor<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, metric: &M) -> Option<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        return self.trees().filter_map(|tree| tree.nearest_neighbor(point, metric))
+            .fold(None, |best, cur| if best.is_none_or(|(_, dist)| cur.1 < dist) { Some(cur) } else { best });
+    }
+
+    /** Find the `k` nearest neighbors to the input point under the distance `metric`: the `k` nearest of every tree, merged.
+     Returns at most `k` points paired with their distances, sorted from closest to farthest. */
+    pub fn k_nearest<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, k: usize, metric: &M) -> Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        let mut found: Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> = self.trees().flat_map(|tree| tree.k_nearest(point, k, metric)).collect();
+        found.sort_by(|(_, d1), (_, d2)| d1.total_order(d2));
+        found.truncate(k);
+        return found;
+    }
+
+    /** Find every point whose distance to the input point lies within `tolerance` of the nearest neighbor's under the
+     distance `metric`, among only the points for which `predicate` returns true. Each tree's ties are gathered, and
+     those within `tolerance` of the nearest across every tree are kept, tree by tree in node list order. */
+    pub fn nearest_ties_filtered<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, tolerance: <P::Scalar as KdScalar>::Dist, metric: &M, predicate: impl Fn(&P) -> bool) -> Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        let found: Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> = self.trees().flat_map(|tree| tree.nearest_ties_filtered(point, tolerance, metric, |p| predicate(p))).collect();
+        let nearest = match found.iter().map(|(_, dist)| *dist).min_by(|d1, d2| d1.total_order(d2)) {
+            Some(nearest) => nearest,
+            None => return found
+        };
+
+        return found.into_iter().filter(|(_, dist)| *dist <= nearest + tolerance).collect();
+    }
+}
+
+impl<P: KdPoint<Scalar = f32>> KdForest<P> {
+    /** Find every point of this forest whose distance to the input point is at most `radius` under the distance `metric`.
+     Returns the matching points paired with their distances, sorted from closest to farthest. */
+    pub fn within_radius<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, radius: f32, metric: &M) -> Vec<(&'t P, f32)> {
+        let mut found: Vec<(&'t P, f32)> = self.trees().flat_map(|tree| tree.within_radius(point, radius, metric)).collect();
+        found.sort_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+        return found;
+    }
+
+    /** Find the nearest neighbor to the input point under the distance `metric` among only the points for which
+     `predicate` returns true, across every tree. Returns the point along with its distance from the input point. */
+    pub fn nearest_neighbor_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, predicate: impl Fn(&P) -> bool) -> Option<(&'t P, f32)> {
+        return self.trees().filter_map(|tree| tree.nearest_neighbor_filtered(point, metric, |p| predicate(p)))
+            .fold(None, |best, cur| if best.is_none_or(|(_, dist)| cur.1 < dist) { Some(cur) } else { best });
+    }
+
+    /** Find the point farthest from the input point under the distance `metric` among only the points for which
+     `predicate` returns true, searching each tree within its own bounding box. Returns the point along with its
+     distance from the input point. */
+    pub fn farthest_neighbor_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, predicate: impl Fn(&P) -> bool) -> Option<(&'t P, f32)> {
+        return self.trees().filter_map(|tree| tree.farthest_neighbor_filtered(point, metric, &tree.bounding_box(), |p| predicate(p)))
+            .fold(None, |best, cur| if best.is_none_or(|(_, dist)| cur.1 > dist) { Some(cur) } else { best });
+    }
+
+    /** Find the `k` points closest to the segment from `a` to `b` among only the points for which `predicate` returns
+     true, as `CKDTree::nearest_to_segment_filtered` does: the `k` closest of every tree, merged. Sorted from closest
+     to farthest. */
+    pub fn nearest_to_segment_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized>(&'t self, a: &Q, b: &Q, k: usize, predicate: impl Fn(&P) -> bool) -> Vec<(&'t P, f32, f32)> {
+        let mut found: Vec<(&'t P, f32, f32)> = self.trees().flat_map(|tree| tree.nearest_to_segment_filtered(a, b, k, |p| predicate(p))).collect();
+        found.sort_by(|(_, d1, _), (_, d2, _)| d1.total_cmp(d2));
+        found.truncate(k);
+        return found;
+    }
+}
+
+/** Collects points into a `KdForest` split along cycling axes, filling the slots the binary digits of the point count
+ call for directly rather than pushing the points one at a time. The dimension of the forest is taken from the first
+ point; an empty iterator produces an empty forest of dimension 0, which takes its dimension from the first point pushed. */
+impl<P: KdPoint> FromIterator<P> for KdForest<P> where P::Scalar: KdScalar {
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        let mut points: Vec<P> = iter.into_iter().collect();
+        let mut forest: KdForest<P> = KdForest::new(points.first().map_or(0, |p| p.dimensions()), SplitRule::CYCLE_AXES);
+        while points.len() > 0 {
+            let slot: usize = points.len().ilog2() as usize;
+            let rest: Vec<P> = points.split_off(1 << slot);
+            forest.m_slots.resize_with(usize::max(forest.m_slots.len(), slot + 1), || None);
+            forest.m_slots[slot] = Some(CKDTree::from_median(forest.m_k, points, forest.m_split_rule));
+            points = rest;
+        }
+
+        return forest;
+    }
+}
+
+/** Pushes the points into the forest one at a time. */
+impl<P: KdPoint> Extend<P> for KdForest<P> where P::Scalar: KdScalar {
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        for point in iter { self.push(point); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KdForest;
+    use crate::kdtree::{AxisBox, CKDTree, SplitRule};
+    use crate::metric::{Euclidean, Manhattan, SquaredEuclidean};
+
+    #[test]
+    fn forest_push_test() {
+        let points: Vec<[f32; 3]> = (0..300).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32, (i % 13) as f32]).collect();
+        let mut forest: KdForest<[f32; 3]> = KdForest::new(3, SplitRule::MAX_VARIANCE);
+        for (n, point) in points.iter().enumerate() {
+            forest.push(*point);
+            assert_eq!(forest.size(), n + 1);
+            assert_eq!(forest.trees().count(), (n + 1).count_ones() as usize);
+        }
+
+        // every tree holds a power of two points and is as shallow as a tree of that many points can be
+        for tree in forest.trees() {
+            assert!(tree.size().is_power_of_two());
+            assert_eq!(tree.depth(), tree.size().ilog2() as usize + 1);
+        }
+
+        let whole: CKDTree<[f32; 3]> = points.iter().copied().collect();
+        for query in [[50.5f32, 40.0, 6.0], [0.0, 0.0, 0.0], [120.0, -3.0, 12.5]] {
+            assert_eq!(forest.nearest_neighbor(&query, &Euclidean).unwrap().1, whole.nearest_neighbor(&query, &Euclidean).unwrap().1);
+            let dists = |found: Vec<(&[f32; 3], f32)>| found.into_iter().map(|(_, dist)| dist).collect::<Vec<f32>>();
+            assert_eq!(dists(forest.k_nearest(&query, 10, &Manhattan)), dists(whole.k_nearest(&query, 10, &Manhattan)));
+            assert_eq!(dists(forest.within_radius(&query, 20.0, &Euclidean)), dists(whole.within_radius(&query, 20.0, &Euclidean)));
+        }
+
+        let bounds: AxisBox = AxisBox::new(3).between(0, 10.0, 60.0).at_least(2, 6.0);
+        assert_eq!(forest.range_search(&bounds).len(), whole.range_search(&bounds).len());
+
+        // the filtered, farthest, segment and tie queries merge the answers of every tree
+        let odd = |p: &[f32; 3]| p[0] as usize % 2 == 1;
+        for query in [[50.5f32, 40.0, 6.0], [120.0, -3.0, 12.5]] {
+            assert_eq!(forest.nearest_neighbor_filtered(&query, &Euclidean, odd).unwrap().1, whole.nearest_neighbor_filtered(&query, &Euclidean, odd).unwrap().1);
+            assert_eq!(forest.farthest_neighbor_filtered(&query, &Euclidean, odd).unwrap().1, whole.farthest_neighbor_filtered(&query, &Euclidean, &whole.bounding_box(), odd).unwrap().1);
+            assert_eq!(forest.nearest_ties_filtered(&query, 1.0, &Manhattan, odd).len(), whole.nearest_ties_filtered(&query, 1.0, &Manhattan, odd).len());
+        }
+
+        let dists = |found: Vec<(&[f32; 3], f32, f32)>| found.into_iter().map(|(_, dist, _)| dist).collect::<Vec<f32>>();
+        assert_eq!(dists(forest.nearest_to_segment_filtered(&[0.0, 0.0, 0.0], &[100.0, 80.0, 12.0], 8, odd)), dists(whole.nearest_to_segment_filtered(&[0.0, 0.0, 0.0], &[100.0, 80.0, 12.0], 8, odd)));
+    }
+
+    #[test]
+    fn forest_remove_and_collect_test() {
+        let mut forest: KdForest<[i16; 2]> = (0..100).map(|i| [(i * 7 % 23) as i16, (i * 5 % 19) as i16 - 9]).collect();
+        assert_eq!(forest.trees().map(|tree| tree.size()).collect::<Vec<usize>>(), vec![4, 32, 64]);
+        forest.extend([[40i16, 40], [-40, -40]]);
+        assert_eq!((forest.size(), forest.trees().count()), (102, 4));
+
+        assert_eq!(forest.nearest_neighbor(&[41i16, 43], &SquaredEuclidean), Some((&[40i16, 40], 10)));
+        assert!(forest.remove(&[40i16, 40]));
+        assert!(!forest.remove(&[40i16, 40]));
+        assert_ne!(forest.nearest_neighbor(&[41i16, 43], &SquaredEuclidean).unwrap().0, &[40i16, 40]);
+        assert_eq!(forest.k_nearest(&[-39i16, -40], 2, &SquaredEuclidean)[0], (&[-40i16, -40], 1));
+        assert_eq!(forest.iter().count(), 101);
+
+        let tree: CKDTree<[i16; 2]> = forest.into_tree();
+        assert_eq!(tree.size(), 101);
+        assert!(tree.range_search(&AxisBox::new(2).at_least(0, 40)).is_empty());
+    }
+
+    #[test]
+    fn forest_compaction_test() {
+        let points: Vec<[i16; 2]> = (0..64).map(|i| [i as i16, (i * 29 % 64) as i16]).collect();
+        let mut forest: KdForest<[i16; 2]> = points.iter().copied().collect();
+        for point in &points[..32] { assert!(forest.remove(point)); }
+        assert_eq!(forest.trees().map(|tree| tree.size()).collect::<Vec<usize>>(), vec![32]);
+
+        // the 33rd removal leaves the one slot less than half full, so its points are spread over the smaller slots
+        assert!(forest.remove(&points[32]));
+        assert_eq!(forest.trees().map(|tree| tree.size()).collect::<Vec<usize>>(), vec![1, 2, 4, 8, 16]);
+        assert!(forest.trees().all(|tree| tree.removed_count() == 0));
+        for query in [[0i16, 0], [40, 10], [70, 70]] {
+            let nearest: i64 = points[33..].iter().map(|p| (0..2).map(|axis| (p[axis] - query[axis]) as i64).map(|d| d * d).sum::<i64>()).min().unwrap();
+            assert_eq!(forest.nearest_neighbor(&query, &SquaredEuclidean).unwrap().1, nearest);
+        }
+
+        for point in &points[33..] { assert!(forest.remove(point)); }
+        assert_eq!((forest.size(), forest.trees().count()), (0, 0));
+    }
+
+    #[test]
+    fn forest_empty_collect_test() {
+        let mut forest: KdForest<[f32; 2]> = std::iter::empty().collect();
+        assert_eq!((forest.dimensions(), forest.size()), (0, 0));
+        forest.push([1.0, 2.0]).push([4.0, 6.0]);
+        assert_eq!(forest.dimensions(), 2);
+        assert_eq!(forest.nearest_neighbor(&[4.0f32, 5.0], &Euclidean), Some((&[4.0f32, 6.0], 1.0)));
+    }
+}
@@ -1,74 +1,131 @@
-use std::ops::{Deref, DerefMut, Index};
 use std::mem;
 use std::fmt::{Debug, Display};
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::collections::{BinaryHeap, VecDeque};
+
+use num::{ToPrimitive, Zero};
+
+use crate::metric::{KdNumber, KdScalar, Metric};
+use crate::spatial_index::{MetricIndex, SpatialIndex};
 
 #[derive(Debug)]
 struct ContiguousKDNode<P> {
     m_axis: usize,
     m_midpoint: P,
     m_dirs: [Option<usize>; 2], // m_dirs[0] -> Left (less than) or equal to, m_dirs[1] -> Greater than
+    m_removed: bool // tombstone: the node still splits space but its point is no longer part of the tree
 }
 
 type CKDNode<P> = ContiguousKDNode<P>;
 
-/** Wrapper type with `Deref` implementation. Allows CKDNodes to accept any type that dereferences into 
-  another type with the index trait. */
-  #[derive(Debug)]
-pub struct CKDWrapper<T>(T);
-impl<T> Deref for CKDWrapper<T> {
-    type Target = T;
-    fn deref(&self) -> &Self::Target {
-        return &self.0;
+/** A point a KD-tree can hold or be queried with: a fixed number of axes and a coordinate along each of them. Trees
+ store their points as they are and compare them through this trait alone, so any point type works once it says how
+ many axes it has and what lies along each. Implemented for arrays, slices, `Vec`s and `nalgebra` vectors, and passed
+ through `Box`, `Rc`, `Arc` and plain references, so a tree can just as well hold shared handles to its points. */
+pub trait KdPoint {
+    type Scalar: PartialOrd + Copy;
+
+    /** Returns the number of axes of this point. */
+    fn dimensions(&self) -> usize;
+
+    /** Returns the coordinate of this point along `axis`. */
+    fn coord(&self, axis: usize) -> Self::Scalar;
+}
+
+impl<T: PartialOrd + Copy, const N: usize> KdPoint for [T; N] {
+    type Scalar = T;
+
+    fn dimensions(&self) -> usize {
+        return N;
+    }
+
+    fn coord(&self, axis: usize) -> T {
+        return self[axis];
     }
 }
 
-impl<T> DerefMut for CKDWrapper<T> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        return &mut self.0;
+impl<T: PartialOrd + Copy> KdPoint for [T] {
+    type Scalar = T;
+
+    fn dimensions(&self) -> usize {
+        return self.len();
+    }
+
+    fn coord(&self, axis: usize) -> T {
+        return self[axis];
     }
 }
 
-impl<T: Display> Display for CKDWrapper<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return self.0.fmt(f);
+impl<T: PartialOrd + Copy> KdPoint for Vec<T> {
+    type Scalar = T;
+
+    fn dimensions(&self) -> usize {
+        return self.len();
+    }
+
+    fn coord(&self, axis: usize) -> T {
+        return self[axis];
     }
 }
 
-#[derive(Debug)]
-pub struct ContiguousKDTree<P> {
-    m_k: usize,
-    m_nodelist: Vec<CKDNode<P>>
+impl<T: na::Scalar + PartialOrd + Copy, const D: usize> KdPoint for na::SVector<T, D> {
+    type Scalar = T;
+
+    fn dimensions(&self) -> usize {
+        return D;
+    }
+
+    fn coord(&self, axis: usize) -> T {
+        return self[axis];
+    }
 }
 
-pub type CKDTree<P> = ContiguousKDTree<P>;
+/** Forwards `KdPoint` through a pointer type to the point it points to. */
+macro_rules! kd_point_through {
+    ($($pointer:ty),*) => {$(
+        impl<T: KdPoint + ?Sized> KdPoint for $pointer {
+            type Scalar = T::Scalar;
 
-impl<T: Index<usize>> CKDNode<CKDWrapper<T>> {
+            fn dimensions(&self) -> usize {
+                return (**self).dimensions();
+            }
 
-    /** Construct a `CKDNode` given some point `T`, where T has the trait `Index<usize>`. T
-     is moved into the node and wrapped inside a `CKDWrapper` that implements `Deref<T>`. 
-     The type of the returned CKDNode can be thought of is `CDKNode<CKDWrapper<T>>`.*/
-    fn new(axis: usize, point: T, left_idx: Option<usize>, right_idx: Option<usize>) -> Self {
-        return CKDNode {
-            m_axis: axis,
-            m_midpoint: CKDWrapper(point),
-            m_dirs: [left_idx, right_idx]
+            fn coord(&self, axis: usize) -> T::Scalar {
+                return (**self).coord(axis);
+            }
         }
-    }
+    )*};
+}
 
-    /** Construct an <b>"empty"</b> `CKDNode` given some point `T`, where T has the trait `Index<usize>`. T
-     is moved into the node and wrapped inside a `CKDWrapper` that implements `Deref<T>`. 
-     The type of the returned CKDNode can be thought of is `CDKNode<CKDWrapper<T>>`.*/
-    fn new_empty(axis: usize, point: T) -> Self {
-        return Self::new(axis, point, None, None);
-    }
+kd_point_through!(&T, Box<T>, Rc<T>, Arc<T>);
+
+/** Decides which axis a node splits on when a `CKDTree` is bulk built from median splits. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitRule {
+    CYCLE_AXES = 0,  // axis of a child is the axis of its parent plus one, as with `push`
+    MAX_VARIANCE = 1 // axis with the highest variance among the points of the subtree
 }
 
+#[derive(Debug)]
+pub struct ContiguousKDTree<P> {
+    m_k: usize,
+    m_nodelist: Vec<CKDNode<P>>,
+    m_split_rule: SplitRule,
+    m_removed_count: usize,
+    m_rebuild_ratio: f32 // fraction of removed nodes at which the tree is rebuilt
+}
+
+pub type CKDTree<P> = ContiguousKDTree<P>;
+
 pub struct Iter<'a, P> {
     m_buffer: &'a Vec<CKDNode<P>>,
     m_next: usize
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum VisitState {
     Visited = 1,
     NotVisited = 0
@@ -76,38 +133,448 @@ enum VisitState {
 
 type StackLayer = (usize, VisitState);
 
+/** A node index paired with the distance of its point from some query point. Ordered by distance alone so that a
+ `BinaryHeap<NeighborEntry>` behaves as a max-heap keyed on distance. */
+struct NeighborEntry<D = f32> {
+    m_dist: D,
+    m_idx: usize
+}
+
+impl<D: KdNumber> PartialEq for NeighborEntry<D> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl<D: KdNumber> Eq for NeighborEntry<D> {}
+
+impl<D: KdNumber> PartialOrd for NeighborEntry<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl<D: KdNumber> Ord for NeighborEntry<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self.m_dist.total_order(&other.m_dist);
+    }
+}
+
+/** An entry on the frontier of a `NearestIter`: either a node whose subtree is still unexplored, keyed on a lower bound
+ of the distance to anything in it, or a single point, keyed on its exact distance. Ordered so that a `BinaryHeap`
+ behaves as a min-heap on distance. Nodes are popped before points at the same distance, since they may still hold a
+ point tied with them, and tied points are popped by `node_rank`, lowest first, the order `nearest_neighbor` and
+ `nearest_ties` break ties in. */
+#[derive(Debug)]
+struct FrontierEntry {
+    m_dist: f32,
+    m_idx: usize,
+    m_is_point: bool,
+    m_rank: usize // the node_rank of a point, 0 for a node
+}
+
+impl PartialEq for FrontierEntry {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for FrontierEntry {}
+
+impl PartialOrd for FrontierEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for FrontierEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other.m_dist.total_cmp(&self.m_dist).then(other.m_is_point.cmp(&self.m_is_point)).then(other.m_rank.cmp(&self.m_rank));
+    }
+}
+
+/** Lazily yields the points of a tree in increasing distance from a query point, best-first: a priority queue holds both
+ unexplored subtrees (keyed on a lower bound of their distance) and points (keyed on their exact distance), and a point
+ is only yielded once nothing left in the queue can be closer. Nothing is searched until asked for, so callers can
+ `take_while` on distance or stop at the first point passing a filter without picking a `k` up front. Built by
+ `CKDTree::nearest_iter` and `KdTree::nearest_iter`. */
+pub struct NearestIter<'t, L, Q: ?Sized, M> {
+    m_tree: &'t L,
+    m_point: &'t Q,
+    m_metric: &'t M,
+    m_frontier: BinaryHeap<FrontierEntry>,
+    m_visited: usize
+}
+
+impl<'t, L, Q: ?Sized, M> NearestIter<'t, L, Q, M> {
+    /** Returns the number of tree nodes expanded so far. */
+    pub fn visited(&self) -> usize {
+        return self.m_visited;
+    }
+}
+
+impl<'t, L, Q, M> NearestIter<'t, L, Q, M>
+where
+    L: NodeList,
+    L::Point: KdPoint<Scalar = f32>,
+    Q: KdPoint<Scalar = f32> + ?Sized,
+    M: Metric, {
+    fn new(tree: &'t L, point: &'t Q, metric: &'t M) -> Self {
+        let mut frontier: BinaryHeap<FrontierEntry> = BinaryHeap::new();
+        if tree.node_count() > 0 { frontier.push(FrontierEntry { m_dist: 0f32, m_idx: 0, m_is_point: false, m_rank: 0 }); }
+        return NearestIter { m_tree: tree, m_point: point, m_metric: metric, m_frontier: frontier, m_visited: 0 };
+    }
+
+    /** Expands nodes off the frontier until a point reaches its front, returning that point's node index and distance. A
+     node's near child inherits its bound, while its far child's bound is raised to the distance of the splitting plane. */
+    fn next_node(&mut self) -> Option<(usize, f32)> {
+        while let Some(entry) = self.m_frontier.pop() {
+            if entry.m_is_point { return Some((entry.m_idx, entry.m_dist)); }
+
+            let (tree, point, cur_idx): (&L, &Q, usize) = (self.m_tree, self.m_point, entry.m_idx);
+            self.m_visited += 1;
+            if !tree.node_removed(cur_idx) {
+                let dist: f32 = self.m_metric.distance(tree.node_point(cur_idx), point, tree.axis_count());
+                self.m_frontier.push(FrontierEntry { m_dist: dist, m_idx: cur_idx, m_is_point: true, m_rank: tree.node_rank(cur_idx) });
+            }
+
+            if let Some(near_idx) = tree.node_travel(cur_idx, point) {
+                self.m_frontier.push(FrontierEntry { m_dist: entry.m_dist, m_idx: near_idx, m_is_point: false, m_rank: 0 });
+            }
+
+            if let Some(far_idx) = tree.node_travel_invert(cur_idx, point) {
+                let bound: f32 = entry.m_dist.max(tree.node_plane_distance(cur_idx, point, self.m_metric));
+                self.m_frontier.push(FrontierEntry { m_dist: bound, m_idx: far_idx, m_is_point: false, m_rank: 0 });
+            }
+        }
+
+        return None;
+    }
+}
+
+impl<'t, P, Q, M> Iterator for NearestIter<'t, CKDTree<P>, Q, M>
+where
+    P: KdPoint<Scalar = f32>,
+    Q: KdPoint<Scalar = f32> + ?Sized,
+    M: Metric, {
+    type Item = (&'t P, f32);
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree: &'t CKDTree<P> = self.m_tree;
+        return self.next_node().map(|(idx, dist)| (tree.node_point(idx), dist));
+    }
+}
+
+impl<'t, const K: usize, M: Metric> Iterator for NearestIter<'t, KdTree<K>, [f32; K], M> {
+    type Item = (usize, f32);
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree: &'t KdTree<K> = self.m_tree;
+        return self.next_node().map(|(idx, dist)| (tree.m_payloads[idx], dist));
+    }
+}
+
+/** An axis-aligned box used for orthogonal range searches, over coordinates of type `S`. Every axis has an inclusive
+ lower and upper bound, and a side left unbounded is represented by the lowest or highest value of `S` (an infinity
+ for floats). */
+#[derive(Debug, Clone)]
+pub struct AxisBox<S = f32> {
+    m_lower: Vec<S>,
+    m_upper: Vec<S>
+}
+
+impl<S: KdScalar> AxisBox<S> {
+    /** Creates a box over `dimensions` axes that is unbounded along every one of them. */
+    pub fn new(dimensions: usize) -> Self {
+        return AxisBox {
+            m_lower: vec![S::lowest(); dimensions],
+            m_upper: vec![S::highest(); dimensions]
+        };
+    }
+
+    /** Restricts `axis` to the inclusive range [lower, upper]. */
+    pub fn between(mut self, axis: usize, lower: S, upper: S) -> Self {
+        self.m_lower[axis] = lower;
+        self.m_upper[axis] = upper;
+        return self;
+    }
+
+    /** Restricts `axis` to values greater than or equal to `lower`. */
+    pub fn at_least(mut self, axis: usize, lower: S) -> Self {
+        self.m_lower[axis] = lower;
+        return self;
+    }
+
+    /** Restricts `axis` to values less than or equal to `upper`. */
+    pub fn at_most(mut self, axis: usize, upper: S) -> Self {
+        self.m_upper[axis] = upper;
+        return self;
+    }
+
+    /** Creates a box over `dimensions` axes that contains nothing, ready to `enclose` points. */
+    pub fn empty(dimensions: usize) -> Self {
+        return AxisBox {
+            m_lower: vec![S::highest(); dimensions],
+            m_upper: vec![S::lowest(); dimensions]
+        };
+    }
+
+    /** Grows this box just enough to contain the given point. */
+    pub fn enclose<T: KdPoint<Scalar = S> + ?Sized>(&mut self, point: &T) -> &mut Self {
+        for axis in 0..self.dimensions() {
+            if point.coord(axis) < self.m_lower[axis] { self.m_lower[axis] = point.coord(axis); }
+            if point.coord(axis) > self.m_upper[axis] { self.m_upper[axis] = point.coord(axis); }
+        }
+
+        return self;
+    }
+
+    /** Returns the number of axes this box spans. */
+    pub fn dimensions(&self) -> usize {
+        return self.m_lower.len();
+    }
+
+    /** Checks whether the given point lies inside this box (bounds inclusive). */
+    pub fn contains<T: KdPoint<Scalar = S> + ?Sized>(&self, point: &T) -> bool {
+        return (0..self.dimensions()).all(|axis| self.m_lower[axis] <= point.coord(axis) && point.coord(axis) <= self.m_upper[axis]);
+    }
+}
+
+/** The result of `CKDTree::approx_nearest`: the point found, its distance from the query point and the number of
+ tree nodes the search had to visit to find it. */
+#[derive(Debug)]
+pub struct ApproxNeighbor<'t, T: ?Sized> {
+    m_point: &'t T,
+    m_dist: f32,
+    m_visited: usize,
+    m_node: usize // index of the node holding the point
+}
+
+/** Where in a tree a nearest neighbor query found its answer, to be handed to the next query as a warm start. A hint only
+ ever provides an initial upper bound, so one left stale by later changes to the tree still gives correct answers. */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearestHint(usize);
+
+impl<'t, T: ?Sized> ApproxNeighbor<'t, T> {
+    pub fn point(&self) -> &'t T {
+        return self.m_point;
+    }
+
+    pub fn distance(&self) -> f32 {
+        return self.m_dist;
+    }
+
+    pub fn visited(&self) -> usize {
+        return self.m_visited;
+    }
+
+    /** Returns a hint to warm start the next query from this one's answer. */
+    pub fn hint(&self) -> NearestHint {
+        return NearestHint(self.m_node);
+    }
+}
+
+/** One step of a nearest neighbor search, as recorded by the `*_logged` queries. Nodes are named by their index in the
+ tree's node list. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchEvent {
+    DESCEND { node: usize },                               // entered the node on the way down towards the query point
+    CANDIDATE { node: usize, dist: f32 },                  // the node's point became the nearest one found so far
+    BACKTRACK { node: usize },                             // came back up to the node once the subtree towards the query point was done
+    CROSS { node: usize, child: usize },                   // about to search the child on the far side of the node's splitting plane
+    PRUNE { node: usize, child: usize, bound: f32, best: f32 } // skipped that child, as its bound could not beat the best distance
+}
+
+impl SearchEvent {
+    /** Writes the event as a JSON object, naming every node by its index along with `label` of it. */
+    pub fn to_json(&self, label: impl Fn(usize) -> String) -> String {
+        let named = |key: &str, idx: usize| format!("\"{}\":{},\"{}_label\":{}", key, idx, key, json_string(&label(idx)));
+        return match self {
+            SearchEvent::DESCEND { node } => format!("{{\"event\":\"DESCEND\",{}}}", named("node", *node)),
+            SearchEvent::CANDIDATE { node, dist } => format!("{{\"event\":\"CANDIDATE\",{},\"distance\":{}}}", named("node", *node), dist),
+            SearchEvent::BACKTRACK { node } => format!("{{\"event\":\"BACKTRACK\",{}}}", named("node", *node)),
+            SearchEvent::CROSS { node, child } => format!("{{\"event\":\"CROSS\",{},{}}}", named("node", *node), named("child", *child)),
+            SearchEvent::PRUNE { node, child, bound, best } =>
+                format!("{{\"event\":\"PRUNE\",{},{},\"bound\":{},\"best\":{}}}", named("node", *node), named("child", *child), bound, best)
+        };
+    }
+}
+
+/** Counts of the work a single query did: the tree nodes it visited, the distances it evaluated and the subtrees
+ it pruned without entering. Returned by the `*_traced` queries. A trace made by `QueryTrace::logging` additionally
+ keeps an ordered log of every `SearchEvent` of a nearest neighbor search, which the `*_logged` queries return. */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct QueryTrace {
+    m_visited: usize,
+    m_distance_evals: usize,
+    m_pruned: usize,
+    m_events: Option<Vec<SearchEvent>>
+}
+
+impl QueryTrace {
+    /** Creates an empty trace that also logs the events of the search it is passed to. */
+    pub fn logging() -> Self {
+        return QueryTrace { m_events: Some(Vec::new()), ..QueryTrace::default() };
+    }
+
+    /** Appends `event` to the log, if this trace keeps one. */
+    fn log(&mut self, event: SearchEvent) {
+        if let Some(events) = &mut self.m_events { events.push(event); }
+    }
+
+    /** Returns the logged events in the order they happened, or nothing if this trace keeps no log. */
+    pub fn events(&self) -> &[SearchEvent] {
+        return self.m_events.as_deref().unwrap_or(&[]);
+    }
+
+    pub fn visited(&self) -> usize {
+        return self.m_visited;
+    }
+
+    pub fn distance_evals(&self) -> usize {
+        return self.m_distance_evals;
+    }
+
+    pub fn pruned(&self) -> usize {
+        return self.m_pruned;
+    }
+
+    pub fn to_json(&self) -> String {
+        return format!("{{\"visited\":{},\"distance_evals\":{},\"pruned\":{}}}", self.m_visited, self.m_distance_evals, self.m_pruned);
+    }
+}
+
+/** A summary of the shape of a `CKDTree`. Depths count nodes from the root, so a lone root is at depth 1. The balance
+ factor is the depth divided by the smallest depth any binary tree holding the same number of nodes could have, so a
+ perfectly balanced tree scores 1 and a tree degenerated into a list scores n / log2(n + 1). */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    m_nodes: usize,
+    m_removed: usize,
+    m_depth: usize,
+    m_min_leaf_depth: usize,
+    m_mean_leaf_depth: f32,
+    m_max_leaf_depth: usize,
+    m_axis_splits: Vec<usize>, // number of internal nodes splitting along each axis
+    m_balance: f32
+}
+
+impl TreeStats {
+    pub fn nodes(&self) -> usize {
+        return self.m_nodes;
+    }
+
+    pub fn removed(&self) -> usize {
+        return self.m_removed;
+    }
+
+    pub fn depth(&self) -> usize {
+        return self.m_depth;
+    }
+
+    pub fn min_leaf_depth(&self) -> usize {
+        return self.m_min_leaf_depth;
+    }
+
+    pub fn mean_leaf_depth(&self) -> f32 {
+        return self.m_mean_leaf_depth;
+    }
+
+    pub fn max_leaf_depth(&self) -> usize {
+        return self.m_max_leaf_depth;
+    }
+
+    pub fn axis_splits(&self) -> &[usize] {
+        return &self.m_axis_splits;
+    }
+
+    pub fn balance(&self) -> f32 {
+        return self.m_balance;
+    }
+
+    /** Serialises these stats as a JSON object, labelling the per-axis split counts with `axis_names`. */
+    pub fn to_json(&self, axis_names: &[&str]) -> String {
+        let splits: Vec<String> = self.m_axis_splits.iter().enumerate()
+            .map(|(axis, count)| format!("{}:{}", json_string(&axis_name(axis_names, axis)), count))
+            .collect();
+
+        return format!(
+            "{{\"nodes\":{},\"removed\":{},\"depth\":{},\"min_leaf_depth\":{},\"mean_leaf_depth\":{},\"max_leaf_depth\":{},\"axis_splits\":{{{}}},\"balance\":{}}}",
+            self.m_nodes, self.m_removed, self.m_depth, self.m_min_leaf_depth, self.m_mean_leaf_depth, self.m_max_leaf_depth, splits.join(","), self.m_balance
+        );
+    }
+}
+
 pub enum TreeOrder {
     IN_ORDER = 0,
-    PRE_ORDER = 1
+    PRE_ORDER = 1,
+    POST_ORDER = 2,
+    LEVEL_ORDER = 3
+}
+
+/** A node in the middle of a `TreeIter` traversal, along with where it sits in the tree. */
+#[derive(Debug, Clone)]
+struct TreeLayer {
+    m_idx: usize,
+    m_state: VisitState,
+    m_depth: usize,
+    m_parent: Option<usize>
 }
 
 pub struct TreeIter<'a, P> {
     m_buffer: &'a Vec<CKDNode<P>>,
-    m_stack: Vec<StackLayer>,
+    m_stack: VecDeque<TreeLayer>,
     m_order: TreeOrder
 }
 
-pub struct TreeTravelIter<'a, 'b, P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize> {
+/** A node yielded by a `TreeIter`: its index in the tree's node list, split axis and point, together with its
+ depth (the root is at depth 0) and the index of its parent (None for the root). */
+#[derive(Debug)]
+pub struct TreeNode<'a, T: ?Sized> {
+    m_index: usize,
+    m_axis: usize,
+    m_point: &'a T,
+    m_depth: usize,
+    m_parent: Option<usize>
+}
+
+impl<'a, T: ?Sized> TreeNode<'a, T> {
+    pub fn index(&self) -> usize {
+        return self.m_index;
+    }
+
+    pub fn axis(&self) -> usize {
+        return self.m_axis;
+    }
+
+    pub fn point(&self) -> &'a T {
+        return self.m_point;
+    }
+
+    pub fn depth(&self) -> usize {
+        return self.m_depth;
+    }
+
+    pub fn parent(&self) -> Option<usize> {
+        return self.m_parent;
+    }
+}
+
+pub struct TreeTravelIter<'a, 'b, P, Q: ?Sized> {
     m_node_list: &'a Vec<CKDNode<P>>,
     m_node_at: Option<&'a CKDNode<P>>,
-    m_ref_point: &'b <P as Deref>::Target
+    m_ref_point: &'b Q
 }
 
-impl<'a, 'b, P> Iterator for TreeTravelIter<'a, 'b, P> 
+impl<'a, 'b, P, Q> Iterator for TreeTravelIter<'a, 'b, P, Q> 
 where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize>,
-    <<P as Deref>::Target as Index<usize>>::Output: PartialOrd + Copy {
-    type Item = (usize, &'a <P as Deref>::Target);
+    P: KdPoint,
+    Q: KdPoint<Scalar = P::Scalar> + ?Sized {
+    type Item = (usize, &'a P);
 
     // Position the TreeIterator to the next item in the list 
     fn next(&mut self) -> Option<Self::Item> {
         return self.m_node_at.and_then(|node: &CKDNode<P>| {
             self.m_node_at = node.travel(self.m_ref_point).and_then(|idx| Some(&self.m_node_list[idx]));
-            Some((node.m_axis, node.m_midpoint.deref()))
+            Some((node.m_axis, &node.m_midpoint))
         });
     }
 }
@@ -142,117 +609,86 @@ impl<T> CKDNode<T> {
     }
 }
 
-impl<P> CKDNode<P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize>,
-    <<P as Deref>::Target as Index<usize>>::Output: PartialOrd + Copy {
+impl<P: KdPoint> CKDNode<P> {
 
-    /** Construct a `CKDNode` given some point `P`, where P has the trait `Deref<Target = Index<usize>>`. */
-    fn new_from_container(axis: usize, point: P, left_idx: Option<usize>, right_idx: Option<usize>) -> Self {
+    /** Construct a `CKDNode` holding the point `P`. */
+    fn new(axis: usize, point: P, left_idx: Option<usize>, right_idx: Option<usize>) -> Self {
         return CKDNode {
             m_axis: axis,
             m_midpoint: point,
-            m_dirs: [left_idx, right_idx]
+            m_dirs: [left_idx, right_idx],
+            m_removed: false
         };
     }
 
-    /** Constructs an empty `CDKNode` from a "wrapped" inputted point. */
-    fn new_empty_from_container(axis: usize, point: P) -> Self {
-        return Self::new_from_container(axis, point, None, None);
+    /** Construct an <b>"empty"</b> `CKDNode` (one without children) holding the point `P`. */
+    fn new_empty(axis: usize, point: P) -> Self {
+        return Self::new(axis, point, None, None);
     }
 
     /** Compares this node with another point along this nodes axis */
-    fn compare<'other, T>(&self, point: &'other T) -> bool 
-    where T: AsRef<<P as Deref>::Target>, {
-        return point.as_ref()[self.m_axis] > self.m_midpoint[self.m_axis];
+    fn compare<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&self, point: &Q) -> bool {
+        return point.coord(self.m_axis) > self.m_midpoint.coord(self.m_axis);
     }
 
-    /** Returns the value held in this node's `m_midpoint` field when indexed by
-     this node's `m_axis`. */
-    fn get_axis_value(&self) -> <<P as Deref>::Target as Index<usize>>::Output {
-        return self.m_midpoint[self.m_axis];
+    /** Returns the coordinate of this node's `m_midpoint` along this node's `m_axis`. */
+    fn get_axis_value(&self) -> P::Scalar {
+        return self.m_midpoint.coord(self.m_axis);
     }
 
     /** Compares the midpoint of this CKDNode with the borrowed point along the axis of
      this CKDNode. If the input point is GREATER, travel to the right. If it is LESS
      THAN OR EQUAL TO, travel to the left. Returns the index of the next left or right
      node in an Option<usize>. If there is no 'next' in that location None is returned. */
-    fn travel<'other>(&self, point: &'other <P as Deref>::Target) -> Option<usize> {
-        return self.m_dirs[(point[self.m_axis] > self.m_midpoint[self.m_axis]) as usize];
+    fn travel<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&self, point: &Q) -> Option<usize> {
+        return self.m_dirs[self.compare(point) as usize];
     }
 
     /** Compares the midpoint of this CKDNode with the borrowed point along the axis of
      this CKDNode. If the input point is greater, travel to the right. If it is less
      than or equal to, travel to the left. Returns a mutable reference to this CKDNode's left
      or right Option<usize> values. */
-    fn travel_mut<'other>(&mut self, point: &'other <P as Deref>::Target) -> &mut Option<usize> {
-        return &mut self.m_dirs[(point[self.m_axis] > self.m_midpoint[self.m_axis]) as usize];
+    fn travel_mut<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&mut self, point: &Q) -> &mut Option<usize> {
+        return &mut self.m_dirs[self.compare(point) as usize];
     }
 
     /** Can be thought of as the <b>negation</b> of CKDNode::travel().
      Compares the midpoint of this CKDNode with the borrowed point along the axis of
      this CKDNode. If the input point is LESS THAN OR EQUAL TO, travel to the right. If it is 
      GREATER THAN, travel to the left. */
-    fn travel_invert<'other>(&self, point: &'other <P as Deref>::Target) -> Option<usize> {
-        return self.m_dirs[!(point[self.m_axis] > self.m_midpoint[self.m_axis]) as usize];
+    fn travel_invert<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&self, point: &Q) -> Option<usize> {
+        return self.m_dirs[!self.compare(point) as usize];
     }
 
     /** Can be thought of as the <b>negation</b> of CKDNode::travel_mut().
      Compares the midpoint of this CKDNode with the borrowed point along the axis of
      this CKDNode. If the input point is LESS THAN OR EQUAL TO, travel to the right. If it is 
      GREATER THAN, travel to the left. */
-    fn travel_invert_mut<'other>(&mut self, point: &'other <P as Deref>::Target) -> &mut Option<usize> {
-        return &mut self.m_dirs[!(point[self.m_axis] > self.m_midpoint[self.m_axis]) as usize];
-    }
-}
-
-impl<T> CKDTree<CKDWrapper<T>> 
-where 
-    T: Index<usize>,
-    <T as Index<usize>>::Output: PartialOrd + Copy {
-
-    /** Insert an unwrapped Indexable element `T` into the CKDTree as `CKDWrapper<T>` */
-    pub fn push(&mut self, point: T) -> &mut Self {
-        if self.m_nodelist.len() == 0 {
-            self.m_nodelist.push(CKDNode::new_empty(0, point));
-            return self;
-        }
-
-        let new_idx = self.m_nodelist.len();
-        let (last_node, prev_idx): (&mut CKDNode<CKDWrapper<T>>, usize) = self.get_last_mut(&point);
-        
-        _ = mem::replace(last_node.travel_mut(&point), Some(new_idx));
-        self.m_nodelist.push(CKDNode::new_empty(self.next_axis_from_idx(prev_idx), point));
-        return self;
+    fn travel_invert_mut<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&mut self, point: &Q) -> &mut Option<usize> {
+        return &mut self.m_dirs[!self.compare(point) as usize];
     }
 }
 
-impl<P> CKDTree<P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize>,
-    <<P as Deref>::Target as Index<usize>>::Output: PartialOrd + Copy {
+impl<P: KdPoint> CKDTree<P> {
 
     /** Creates a new CKDTree */
     pub fn new(dimensions: usize) -> Self {
         return CKDTree {
             m_k: dimensions,
-            m_nodelist: Vec::new()
+            m_nodelist: Vec::new(),
+            m_split_rule: SplitRule::CYCLE_AXES,
+            m_removed_count: 0,
+            m_rebuild_ratio: 0.25f32
         };
     }
 
     /** Returns the next `&CKDNode` in this `CKDTree` given the current &CKDNode 'node' and a comparison point */
-    fn to_next<'other, T>(&self, node: &CKDNode<P>, point: &'other P) -> Option<&CKDNode<P>>
-    where 
-        T: Deref,
-        <T as Deref>::Target: Index<usize>,
-        <<T as Deref>::Target as Index<usize>>::Output: PartialOrd + Copy {
+    fn to_next(&self, node: &CKDNode<P>, point: &P) -> Option<&CKDNode<P>> {
         return node.travel(point).and_then(|next_idx: usize| Some(&self.m_nodelist[next_idx]));
     }
 
     // Returns the next 
-    fn to_next_mut<'other>(&mut self, node: &mut CKDNode<P>, point: &'other P) -> Option<&mut CKDNode<P>> {
+    fn to_next_mut(&mut self, node: &mut CKDNode<P>, point: &P) -> Option<&mut CKDNode<P>> {
         return node.travel(point).and_then(|next_idx: usize| Some(&mut self.m_nodelist[next_idx]));
     }
 
@@ -276,14 +712,83 @@ where
         return (node.m_axis + 1) % self.m_k;
     }
 
-    /** Returns the size of this CKDNode */
+    /** Returns the number of points in this CKDTree, not counting removed ones */
     pub fn size(&self) -> usize {
-        return self.m_nodelist.len();
+        return self.m_nodelist.len() - self.m_removed_count;
+    }
+
+    /** Returns the number of removed nodes still occupying space in this CKDTree */
+    pub fn removed_count(&self) -> usize {
+        return self.m_removed_count;
+    }
+
+    /** Sets the fraction of removed nodes (in the range (0, 1]) at which `remove` rebuilds the tree. */
+    pub fn set_rebuild_ratio(&mut self, ratio: f32) -> &mut Self {
+        assert!(ratio > 0f32 && ratio <= 1f32, "CKDTree::rebuild_ratio must be within the range (0, 1], you entered {}", ratio);
+        self.m_rebuild_ratio = ratio;
+        return self;
+    }
+
+    /** Returns the number of axes of the points in this CKDTree */
+    pub fn dimensions(&self) -> usize {
+        return self.m_k;
+    }
+
+    /** Returns the rule used to pick split axes whenever this tree is (re)built from median splits. */
+    pub fn split_rule(&self) -> SplitRule {
+        return self.m_split_rule;
+    }
+
+    /** Lays bare every node of this tree in node list order as (split axis, [left index, right index], removed, point),
+     e.g. to serialise the tree. Feeding the nodes back to `from_raw_nodes` restores the exact same tree. */
+    pub fn raw_nodes(&self) -> impl Iterator<Item = (usize, [Option<usize>; 2], bool, &P)> {
+        return self.m_nodelist.iter().map(|node| (node.m_axis, node.m_dirs, node.m_removed, &node.m_midpoint));
+    }
+
+    /** Reassembles a tree from nodes laid out as by `raw_nodes`, without re-inserting any point. Fails unless the nodes form
+     a single tree rooted at index 0 in which every child comes after its parent in the list. */
+    pub fn from_raw_nodes(dimensions: usize, rule: SplitRule, nodes: Vec<(usize, [Option<usize>; 2], bool, P)>) -> Result<Self, &'static str> {
+        check_raw_links(dimensions, nodes.iter().map(|(axis, dirs, _, _)| (*axis, *dirs)))?;
+
+        let mut tree: CKDTree<P> = CKDTree::new(dimensions);
+        tree.m_split_rule = rule;
+        for (axis, dirs, removed, point) in nodes {
+            let mut node: CKDNode<P> = CKDNode::new(axis, point, dirs[0], dirs[1]);
+            node.m_removed = removed;
+            tree.m_removed_count += removed as usize;
+            tree.m_nodelist.push(node);
+        }
+
+        return Ok(tree);
+    }
+
+    /** Drains the node list, returning the points of every node that was not removed. */
+    fn take_live_points(&mut self) -> Vec<P> {
+        self.m_removed_count = 0;
+        return mem::take(&mut self.m_nodelist).into_iter().filter(|node| !node.m_removed).map(|node| node.m_midpoint).collect();
+    }
+
+    /** Consumes this tree, returning the points of every node that was not removed. */
+    pub fn into_points(mut self) -> Vec<P> {
+        return self.take_live_points();
+    }
+
+    /** Returns the number of nodes on the longest path from the root to a leaf (0 for an empty tree). */
+    pub fn depth(&self) -> usize {
+        if self.m_nodelist.len() == 0 { return 0; }
+        let (mut vec_stack, mut max_depth): (Vec<(usize, usize)>, usize) = (vec![(0, 1)], 0);
+
+        while let Some((cur_idx, cur_depth)) = vec_stack.pop() {
+            max_depth = usize::max(max_depth, cur_depth);
+            for next_idx in self.get(cur_idx).m_dirs.iter().flatten() { vec_stack.push((*next_idx, cur_depth + 1)); }
+        }
+
+        return max_depth;
     }
 
     /** Returns a reference to the last CKDNode before the input point (if it were to be inserted),
     along with the index of that point.*/
-    fn get_last<'other>(&self, point: &'other <P as Deref>::Target) -> (&CKDNode<P>, usize) {
+    fn get_last<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&self, point: &Q) -> (&CKDNode<P>, usize) {
         let mut prev_idx: usize = 0;
         let mut next_idx: Option<usize> = Some(0);
         let mut cur_node: &CKDNode<P> = self.get(0);
@@ -300,7 +805,7 @@ where
 
     /** Returns a mutable reference to the last CKDNode before the input point (if it were to be inserted)
     along with the index of that point. */
-    fn get_last_mut<'other>(&mut self, point: &'other <P as Deref>::Target) -> (&mut CKDNode<P>, usize) {
+    fn get_last_mut<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&mut self, point: &Q) -> (&mut CKDNode<P>, usize) {
 
         let mut prev_idx: usize = 0;
         let mut next_idx: Option<usize> = Some(0);
@@ -316,10 +821,11 @@ where
         return unsafe { (cur_node.as_mut().unwrap(), prev_idx) };
     }
 
-    /** Insert a Deref wrapper `W` holding an Indexable `T` into the CKDTree as `W<T>`  */
-    pub fn push_wrapped<'other>(&mut self, point: P) -> &mut Self {
+    /** Inserts `point` into the CKDTree at the end of its travel path, splitting on the axis after its parent's. */
+    pub fn push(&mut self, point: P) -> &mut Self {
         if self.m_nodelist.len() == 0 {
-            self.m_nodelist.push(CKDNode::new_empty_from_container(0, point));
+            if self.m_k == 0 { self.m_k = point.dimensions(); }
+            self.m_nodelist.push(CKDNode::new_empty(0, point));
             return self;
         }
 
@@ -327,7 +833,7 @@ where
         let (last_node, prev_idx): (&mut CKDNode<P>, usize) = self.get_last_mut(&point);
         
         _ = mem::replace(last_node.travel_mut(&point), Some(new_idx));
-        self.m_nodelist.push(CKDNode::new_empty_from_container(self.next_axis_from_idx(prev_idx), point));
+        self.m_nodelist.push(CKDNode::new_empty(self.next_axis_from_idx(prev_idx), point));
         return self;
     }
 
@@ -342,13 +848,16 @@ where
     pub fn tree_iter<'parent>(&'parent self, order: TreeOrder) -> TreeIter<'parent, P> {
         return TreeIter {
             m_buffer: &self.m_nodelist,
-            m_stack: if self.size() == 0 { Vec::new() } else { vec![(0, VisitState::NotVisited)] },
+            m_stack: if self.m_nodelist.len() == 0 { VecDeque::new() } else {
+                VecDeque::from([TreeLayer { m_idx: 0, m_state: VisitState::NotVisited, m_depth: 0, m_parent: None }])
+            },
             m_order: order
         };
     }
 
     /** Constructs an iterator that, given a reference point, travels through the tree and returns all nodes encountered */
-    pub fn tree_travel_iter<'parent, 'other>(&'parent self, point: &'other <P as Deref>::Target) -> TreeTravelIter<'parent, 'other, P> {
+    pub fn tree_travel_iter<'parent, 'other, Q>(&'parent self, point: &'other Q) -> TreeTravelIter<'parent, 'other, P, Q>
+    where Q: KdPoint<Scalar = P::Scalar> + ?Sized {
         return TreeTravelIter {
             m_node_list: &self.m_nodelist,
             m_node_at: self.m_nodelist.get(0),
@@ -357,14 +866,12 @@ where
     }
 }
 
-impl<'parent, P> Iterator for Iter<'parent, P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize> {
+impl<'parent, P> Iterator for Iter<'parent, P> {
 
-    type Item = (usize, &'parent <P as Deref>::Target);
+    type Item = (usize, &'parent P);
     fn next(&mut self) -> Option<Self::Item> {
-        let out = self.m_buffer.get(self.m_next).and_then(| node | Some((node.m_axis, node.m_midpoint.deref()) )); 
+        while self.m_buffer.get(self.m_next).is_some_and(|node| node.m_removed) { self.m_next += 1; }
+        let out = self.m_buffer.get(self.m_next).and_then(| node | Some((node.m_axis, &node.m_midpoint) )); 
         self.m_next += 1;
         return out;
     }
@@ -372,144 +879,1398 @@ where
 
 impl<'a, P> TreeIter<'a,P> {
     fn peek_node(&self) -> &ContiguousKDNode<P> {
-        return &self.m_buffer[self.m_stack[self.m_stack.len() - 1].0];
+        return &self.m_buffer[self.m_stack[self.m_stack.len() - 1].m_idx];
     }
 
-    fn peek(&self) -> Option<&StackLayer> {
-        return self.m_stack.get(self.m_stack.len().wrapping_sub(1));
+    fn peek(&self) -> Option<&TreeLayer> {
+        return self.m_stack.back();
     }
 
-    fn peek_mut(&mut self) -> &mut StackLayer {
-        let len = self.m_stack.len() - 1;
-        return &mut self.m_stack[len];
+    fn peek_mut(&mut self) -> &mut TreeLayer {
+        return self.m_stack.back_mut().unwrap();
     }
 
     fn is_empty(&self) -> bool {
         return self.m_stack.is_empty();
     }
+
+    /** Builds the stack layer for the child at `child_idx` of the node described by `parent`. */
+    fn child_layer(parent: &TreeLayer, child_idx: usize, state: VisitState) -> TreeLayer {
+        return TreeLayer { m_idx: child_idx, m_state: state, m_depth: parent.m_depth + 1, m_parent: Some(parent.m_idx) };
+    }
+
+    /** Pushes the layer for `layer`'s child in direction `dir` (0 -> left, 1 -> right), if that child exists. */
+    fn push_child(&mut self, layer: &TreeLayer, dir: usize, state: VisitState) {
+        if let Some(child_idx) = self.m_buffer[layer.m_idx].m_dirs[dir] {
+            self.m_stack.push_back(Self::child_layer(layer, child_idx, state));
+        }
+    }
 }
 
-impl<'a, P> TreeIter<'a, P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize> {
+impl<'a, P> TreeIter<'a, P> {
 
     /** Advances the iterator to the next node in the kdtree following an in-order heuristic */
-    fn next_in_order<'b>(&'b mut self) -> Option<(usize, &'a <P as Deref>::Target)> {
+    fn next_in_order<'b>(&'b mut self) -> Option<TreeLayer> {
         if self.m_stack.len() == 0 { return None; }
 
-        let stack_data: &mut (usize, VisitState) = self.peek_mut();
-        let cur_idx: usize = stack_data.0; // store now so i don't have to deal with the borrow checker when indexing
-
+        let stack_data: &mut TreeLayer = self.peek_mut();
         return Some(
-            match stack_data.1 {
+            match stack_data.m_state {
                 VisitState::NotVisited => {
-                    stack_data.1 = VisitState::Visited;
-                    let mut cur_node: &ContiguousKDNode<P> = &self.m_buffer[cur_idx];
-                    while cur_node.m_dirs[0].is_some() {
-                        self.m_stack.push((cur_node.m_dirs[0].unwrap(), VisitState::Visited));
-                        cur_node = &self.m_buffer[cur_node.m_dirs[0].unwrap()];
+                    stack_data.m_state = VisitState::Visited;
+                    let mut cur_layer: TreeLayer = stack_data.clone();
+                    while let Some(left_idx) = self.m_buffer[cur_layer.m_idx].m_dirs[0] {
+                        cur_layer = Self::child_layer(&cur_layer, left_idx, VisitState::Visited);
+                        self.m_stack.push_back(cur_layer.clone());
                     }
                     
-                    self.m_stack.pop();
-                    if cur_node.m_dirs[1].is_some() { self.m_stack.push((cur_node.m_dirs[1].unwrap(), VisitState::NotVisited)); }
-                    (cur_node.m_axis, cur_node.m_midpoint.deref()) // output of the match
+                    self.m_stack.pop_back();
+                    self.push_child(&cur_layer, 1, VisitState::NotVisited);
+                    cur_layer // output of the match
                 },
 
                 VisitState::Visited => {
-                    let cur_node: &ContiguousKDNode<P> = &self.m_buffer[cur_idx];
-                    self.m_stack.pop();
-                    if cur_node.m_dirs[1].is_some() { self.m_stack.push((cur_node.m_dirs[1].unwrap(), VisitState::NotVisited)); }
-                    (cur_node.m_axis, cur_node.m_midpoint.deref()) // output of the match (2)
+                    let cur_layer: TreeLayer = self.m_stack.pop_back().unwrap();
+                    self.push_child(&cur_layer, 1, VisitState::NotVisited);
+                    cur_layer // output of the match (2)
                 }
             }
         );
     }
 
     /** Advances the iterator to the next node in the KDTree following a Pre-Order heuristic */
-    fn next_pre_order<'b>(&'b mut self) -> Option<(usize, &'a <P as Deref>::Target)> {
+    fn next_pre_order<'b>(&'b mut self) -> Option<TreeLayer> {
         // backtrack case
-        while let Some((i, VisitState::Visited)) = self.peek() {
-            let backtrack_idx: usize = *i;
-
-            self.m_stack.pop();
-            let backtrack_node: &ContiguousKDNode<P> = &self.m_buffer[backtrack_idx];
-            if backtrack_node.m_dirs[1].is_some() { self.m_stack.push((backtrack_node.m_dirs[1].unwrap(), VisitState::NotVisited)); }
+        while let Some(TreeLayer { m_state: VisitState::Visited, .. }) = self.peek() {
+            let backtrack_layer: TreeLayer = self.m_stack.pop_back().unwrap();
+            self.push_child(&backtrack_layer, 1, VisitState::NotVisited);
         }
 
         if self.is_empty() { return None; } // effectively the "base" case
 
-        let stack_data: &mut (usize, VisitState) = self.peek_mut();
-        let cur_idx: usize = stack_data.0;
-
-        stack_data.1 = VisitState::Visited;
-        let out_node: &'a ContiguousKDNode<P> = &self.m_buffer[cur_idx];
-        if out_node.m_dirs[0].is_some() { self.m_stack.push((out_node.m_dirs[0].unwrap(), VisitState::NotVisited));}
-        return Some((out_node.m_axis, out_node.m_midpoint.deref()));
+        let stack_data: &mut TreeLayer = self.peek_mut();
+        stack_data.m_state = VisitState::Visited;
+        let out_layer: TreeLayer = stack_data.clone();
+        self.push_child(&out_layer, 0, VisitState::NotVisited);
+        return Some(out_layer);
     }
-}
 
-impl<'a, P> Iterator for TreeIter<'a, P> 
-where 
-    P: Deref,
-    <P as Deref>::Target: Index<usize> + Sized {
-    type Item = (usize, &'a <P as Deref>::Target);
-    fn next(&mut self) -> Option<(usize, &'a <P as Deref>::Target)> {
-        match self.m_order {
-            TreeOrder::IN_ORDER => self.next_in_order(),
-            TreeOrder::PRE_ORDER => self.next_pre_order(),
+    /** Advances the iterator to the next node in the KDTree following a Post-Order heuristic. A node is yielded once
+     it is found on top of the stack already visited, at which point both of its subtrees have been yielded. */
+    fn next_post_order<'b>(&'b mut self) -> Option<TreeLayer> {
+        while let Some(TreeLayer { m_state: VisitState::NotVisited, .. }) = self.peek() {
+            let stack_data: &mut TreeLayer = self.peek_mut();
+            stack_data.m_state = VisitState::Visited;
+            let cur_layer: TreeLayer = stack_data.clone();
+            self.push_child(&cur_layer, 1, VisitState::NotVisited); // pushed first so the left subtree is done first
+            self.push_child(&cur_layer, 0, VisitState::NotVisited);
         }
+
+        return self.m_stack.pop_back();
     }
-}
 
-// impl<P> CKDTree<P>
-// where
-//     P: Deref<Target = Vec<f32>> {
+    /** Advances the iterator to the next node in the KDTree following a Level-Order (breadth first) heuristic, where
+     the stack is used as a FIFO queue. */
+    fn next_level_order<'b>(&'b mut self) -> Option<TreeLayer> {
+        let cur_layer: TreeLayer = self.m_stack.pop_front()?;
+        self.push_child(&cur_layer, 0, VisitState::NotVisited);
+        self.push_child(&cur_layer, 1, VisitState::NotVisited);
+        return Some(cur_layer);
+    }
+}
 
-//     pub fn from_median() -> 
-// }
+impl<'a, P> Iterator for TreeIter<'a, P> {
+    type Item = TreeNode<'a, P>;
+    fn next(&mut self) -> Option<TreeNode<'a, P>> {
+        loop { // removed nodes are walked through but never yielded
+            let layer: TreeLayer = match self.m_order {
+                TreeOrder::IN_ORDER => self.next_in_order(),
+                TreeOrder::PRE_ORDER => self.next_pre_order(),
+                TreeOrder::POST_ORDER => self.next_post_order(),
+                TreeOrder::LEVEL_ORDER => self.next_level_order()
+            }?;
+
+            let node: &'a CKDNode<P> = &self.m_buffer[layer.m_idx];
+            if !node.m_removed {
+                return Some(TreeNode {
+                    m_index: layer.m_idx,
+                    m_axis: node.m_axis,
+                    m_point: &node.m_midpoint,
+                    m_depth: layer.m_depth,
+                    m_parent: layer.m_parent
+                });
+            }
+        }
+    }
+}
 
-impl<P> CKDTree<P>
-where
-    P: Deref,
-    <P as Deref>::Target: Index<usize,Output = f32>, {
+impl<P: KdPoint> CKDTree<P> where P::Scalar: KdScalar {
+
+    /** Builds a balanced `CKDTree` from a whole point set at once. Every node holds the median of its subtree's points
+     along its split axis, so the depth of the tree is O(log n) regardless of the order the points come in. The split
+     axis of each node is chosen according to `rule`. Nodes are laid out in pre-order, so the root stays at index 0. A
+     `dimensions` of 0 takes the dimension of the first point, as `push` does. */
+    pub fn from_median(dimensions: usize, points: Vec<P>, rule: SplitRule) -> Self {
+        let dimensions: usize = if dimensions == 0 { points.first().map_or(0, |p| p.dimensions()) } else { dimensions };
+        let mut tree: CKDTree<P> = CKDTree::new(dimensions);
+        tree.m_split_rule = rule;
+        tree.m_nodelist.reserve(points.len());
+        Self::build_median(&mut tree.m_nodelist, points, 0, dimensions, rule);
+        return tree;
+    }
+
+    /** Recursively appends the subtree holding `points` to `nodelist`, returning the index of its root (None if `points` is empty). */
+    fn build_median(nodelist: &mut Vec<CKDNode<P>>, mut points: Vec<P>, cycle_axis: usize, k: usize, rule: SplitRule) -> Option<usize> {
+        if points.is_empty() { return None; }
+
+        let axis: usize = match rule {
+            SplitRule::CYCLE_AXES => cycle_axis,
+            SplitRule::MAX_VARIANCE => Self::max_variance_axis(&points, k)
+        };
+
+        points.sort_by(|p1, p2| p1.coord(axis).total_order(&p2.coord(axis)));
+
+        // points tied with the median along the axis must sit on its left, since `travel` sends ties left
+        let mut median: usize = points.len() / 2;
+        while median + 1 < points.len() && points[median + 1].coord(axis) <= points[median].coord(axis) { median += 1; }
+
+        let right_points: Vec<P> = points.split_off(median + 1);
+        let median_point: P = points.pop().unwrap();
+
+        let node_idx: usize = nodelist.len();
+        nodelist.push(CKDNode::new_empty(axis, median_point));
+        let left_idx: Option<usize> = Self::build_median(nodelist, points, (axis + 1) % k, k, rule);
+        let right_idx: Option<usize> = Self::build_median(nodelist, right_points, (axis + 1) % k, k, rule);
+        nodelist[node_idx].set_left(left_idx).set_right(right_idx);
+        return Some(node_idx);
+    }
+
+    /** Returns the axis along which `points` are the most spread out. The variance only ranks the axes, so it is taken
+     in f32 whatever the coordinate type. */
+    fn max_variance_axis(points: &[P], k: usize) -> usize {
+        let count: f32 = points.len() as f32;
+        let coord = |p: &P, axis: usize| p.coord(axis).to_f32().unwrap_or(0f32);
+        let variance = |axis: usize| {
+            let mean: f32 = points.iter().map(|p| coord(p, axis)).sum::<f32>() / count;
+            return points.iter().map(|p| (coord(p, axis) - mean).powi(2)).sum::<f32>() / count;
+        };
+
+        return (0..k).map(|axis| (axis, variance(axis)))
+            .fold((0, f32::MIN), |best, cur| if cur.1 > best.1 { cur } else { best })
+            .0;
+    }
+
+    /** Rebuilds this tree from median splits using the split rule it was constructed with, balancing it again
+     after a series of `push` calls. Removed nodes are dropped, compacting the node list. */
+    pub fn rebalance(&mut self) -> &mut Self {
+        let points: Vec<P> = self.take_live_points();
+        Self::build_median(&mut self.m_nodelist, points, 0, self.m_k, self.m_split_rule);
+        return self;
+    }
+
+    /** Removes a point equal to `point` (along every axis) from this tree. The node holding it is only tombstoned, so
+     searches skip it from then on while it keeps splitting space; once the fraction of removed nodes reaches the
+     rebuild ratio the tree is rebuilt without them. Returns whether a matching point was found. */
+    pub fn remove<Q: KdPoint<Scalar = P::Scalar> + ?Sized>(&mut self, point: &Q) -> bool {
+        // ties go left on insertion and in median builds, so a stored copy of `point` always lies on its travel path
+        let mut next_idx: Option<usize> = self.m_nodelist.get(0).and(Some(0));
+        while let Some(cur_idx) = next_idx {
+            let cur_node: &CKDNode<P> = self.get(cur_idx);
+            if !cur_node.m_removed && (0..self.m_k).all(|axis| cur_node.m_midpoint.coord(axis) == point.coord(axis)) {
+                self.get_mut(cur_idx).m_removed = true;
+                self.m_removed_count += 1;
+                if self.m_removed_count as f32 >= self.m_rebuild_ratio * self.m_nodelist.len() as f32 { self.rebalance(); }
+                return true;
+            }
+
+            next_idx = cur_node.travel(point);
+        }
+
+        return false;
+    }
+}
+
+/** Collects points into a balanced `CKDTree` built from median splits along cycling axes. The dimension of the tree
+ is taken from the first point; an empty iterator produces an empty tree that takes its dimension from the first point
+ later pushed or extended into it. */
+impl<P: KdPoint> FromIterator<P> for CKDTree<P> where P::Scalar: KdScalar {
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        let points: Vec<P> = iter.into_iter().collect();
+        let dimensions: usize = points.first().map_or(0, |p| p.dimensions());
+        return CKDTree::from_median(dimensions, points, SplitRule::CYCLE_AXES);
+    }
+}
+
+/** Adds the points to the tree, then rebuilds it from median splits so it stays balanced. */
+impl<P: KdPoint> Extend<P> for CKDTree<P> where P::Scalar: KdScalar {
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        let mut points: Vec<P> = self.take_live_points();
+        points.extend(iter);
+        if self.m_k == 0 { self.m_k = points.first().map_or(0, |p| p.dimensions()); }
+        Self::build_median(&mut self.m_nodelist, points, 0, self.m_k, self.m_split_rule);
+    }
+}
+
+/** Read access to a KD-tree stored as a flat list of nodes rooted at index 0, whatever the layout of those nodes. Every
+ search is written once against this trait, in terms of node indices, and shared by `CKDTree` and `KdTree`; each tree
+ then turns the indices it gets back into its own kind of result. The nearest, ties, k-nearest and range searches run
+ over any `KdScalar` coordinates, while the rest need f32 points. */
+pub(crate) trait NodeList {
+    type Point: KdPoint + ?Sized;
+
+    fn node_count(&self) -> usize;
+    fn axis_count(&self) -> usize;
+    fn node_axis(&self, idx: usize) -> usize;
+    fn node_dirs(&self, idx: usize) -> [Option<usize>; 2];
+    fn node_removed(&self, idx: usize) -> bool;
+    fn node_point(&self, idx: usize) -> &Self::Point;
+
+    /** The rank that orders nodes lying at exactly the same distance from a query point, lowest first, so the answer to
+     the query does not depend on where its search started. The node index by default. */
+    fn node_rank(&self, idx: usize) -> usize {
+        return idx;
+    }
+
+    /** Checks whether the node at `idx` has no children. */
+    fn node_is_leaf(&self, idx: usize) -> bool {
+        let dirs: [Option<usize>; 2] = self.node_dirs(idx);
+        return dirs[0].is_none() && dirs[1].is_none();
+    }
+
+    /** The child of the node at `idx` on the same side of its splitting plane as `point` (ties go left). */
+    fn node_travel<Q: KdPoint<Scalar = <Self::Point as KdPoint>::Scalar> + ?Sized>(&self, idx: usize, point: &Q) -> Option<usize> {
+        let axis: usize = self.node_axis(idx);
+        return self.node_dirs(idx)[(point.coord(axis) > self.node_point(idx).coord(axis)) as usize];
+    }
+
+    /** The child of the node at `idx` on the other side of its splitting plane from `point`. */
+    fn node_travel_invert<Q: KdPoint<Scalar = <Self::Point as KdPoint>::Scalar> + ?Sized>(&self, idx: usize, point: &Q) -> Option<usize> {
+        let axis: usize = self.node_axis(idx);
+        return self.node_dirs(idx)[!(point.coord(axis) > self.node_point(idx).coord(axis)) as usize];
+    }
+
+    /** A lower bound, under `metric`, on the distance from `point` to anything on the other side of the splitting plane of the node at `idx`. */
+    fn node_plane_distance<S: KdScalar, Q: KdPoint<Scalar = S> + ?Sized, M: Metric<S>>(&self, idx: usize, point: &Q, metric: &M) -> S::Dist where Self::Point: KdPoint<Scalar = S> {
+        let axis: usize = self.node_axis(idx);
+        return metric.axis_distance(axis, self.node_point(idx).coord(axis).gap(point.coord(axis)));
+    }
+
+    /** Walks every node, removed ones included, to summarise the shape of the tree. */
+    fn shape_stats(&self) -> TreeStats {
+        let mut stats: TreeStats = TreeStats {
+            m_nodes: self.node_count(), m_removed: 0, m_depth: 0, m_min_leaf_depth: 0,
+            m_mean_leaf_depth: 0f32, m_max_leaf_depth: 0, m_axis_splits: vec![0; self.axis_count()], m_balance: 1f32
+        };
+
+        if self.node_count() == 0 { return stats; }
+        let (mut vec_stack, mut leaf_count, mut leaf_depth_sum): (Vec<(usize, usize)>, usize, usize) = (vec![(0, 1)], 0, 0);
+        stats.m_min_leaf_depth = usize::MAX;
+
+        while let Some((cur_idx, cur_depth)) = vec_stack.pop() {
+            stats.m_removed += self.node_removed(cur_idx) as usize;
+            if self.node_is_leaf(cur_idx) {
+                (leaf_count, leaf_depth_sum) = (leaf_count + 1, leaf_depth_sum + cur_depth);
+                stats.m_min_leaf_depth = usize::min(stats.m_min_leaf_depth, cur_depth);
+                stats.m_max_leaf_depth = usize::max(stats.m_max_leaf_depth, cur_depth);
+            } else {
+                stats.m_axis_splits[self.node_axis(cur_idx)] += 1;
+            }
+
+            for next_idx in self.node_dirs(cur_idx).iter().flatten() { vec_stack.push((*next_idx, cur_depth + 1)); }
+        }
+
+        stats.m_depth = stats.m_max_leaf_depth;
+        stats.m_mean_leaf_depth = leaf_depth_sum as f32 / leaf_count as f32;
+        stats.m_balance = stats.m_depth as f32 / (self.node_count() as f32 + 1f32).log2().ceil();
+        return stats;
+    }
+
+    /** The search behind every exact nearest neighbor query, over any `KdScalar` coordinates: descends to the input point's
+     leaf, then backtracks and explores the far side of a splitting plane only if it is no farther than the nearest
+     distance so far. Nodes whose index `predicate` rejects are never candidates. The work done is tallied into `trace`.
+     Returns the index of the nearest node along with its distance. */
+    fn search_nearest<S: KdScalar, Q: KdPoint<Scalar = S> + ?Sized, M: Metric<S>>(&self, point: &Q, metric: &M, predicate: impl Fn(usize) -> bool, trace: &mut QueryTrace) -> Option<(usize, S::Dist)> where Self::Point: KdPoint<Scalar = S> {
+        return self.search_nearest_by(
+            point, None,
+            |idx| metric.distance(self.node_point(idx), point, self.axis_count()),
+            |idx| self.node_plane_distance(idx, point, metric),
+            |bound, best| bound <= best,
+            predicate, trace
+        );
+    }
+
+    /** `search_nearest` over f32 points, warm started from the node at index `start` and approximate: its distance bounds
+     the search from the outset, so subtrees that cannot beat it are pruned right away, and the far side of a splitting
+     plane is only explored if it is no farther than `min_dist / (1 + eps)`. Any live node the predicate accepts gives a
+     valid bound; a `start` that is out of range, removed or rejected is ignored. */
+    fn search_nearest_from<Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&self, point: &Q, start: Option<usize>, eps: f32, metric: &M, predicate: impl Fn(usize) -> bool, trace: &mut QueryTrace) -> Option<(usize, f32)> where Self::Point: KdPoint<Scalar = f32> {
+        let shrink: f32 = 1f32 / (1f32 + eps);
+        return self.search_nearest_by(
+            point, start,
+            |idx| metric.distance(self.node_point(idx), point, self.axis_count()),
+            |idx| self.node_plane_distance(idx, point, metric),
+            |bound, best| bound <= best * shrink,
+            predicate, trace
+        );
+    }
 
-    /** Find the nearest neighbor to the input point among the points stored in this `CKDTree` based upon a distance function `distance_func()`.*/
-    pub fn nearest_neighbor<'t>(&'t self, point: & <P as Deref>::Target, distance_func: impl Fn(& <P as Deref>::Target, & <P as Deref>::Target) -> f32) -> Option<&<P as Deref>::Target> {
-        if self.m_nodelist.len() == 0 { return None; }
-        type Point<P> = <P as Deref>::Target;
-        let (mut vec_stack, mut nearest, mut min_dist) = (vec![(0, VisitState::NotVisited)], None, f32::MAX);
+    /** The walk shared by the nearest neighbor searches, in whatever units `distance` measures: `distance` of a node is that
+     of its point from the input point, `plane_distance` a lower bound on the distance to anything on the far side of its
+     splitting plane, and `crosses` decides from that bound and the best distance so far whether the far side is explored.
+     Nodes at exactly the same distance are told apart by `node_rank`, so as long as `crosses` explores sides whose bound
+     equals the best distance, a warm started search lands on the same node as a cold one. */
+    fn search_nearest_by<Q, D>(
+        &self, point: &Q, start: Option<usize>, distance: impl Fn(usize) -> D, plane_distance: impl Fn(usize) -> D,
+        crosses: impl Fn(D, D) -> bool, predicate: impl Fn(usize) -> bool, trace: &mut QueryTrace
+    ) -> Option<(usize, D)>
+    where
+        Q: KdPoint<Scalar = <Self::Point as KdPoint>::Scalar> + ?Sized,
+        D: ToPrimitive + PartialOrd + Copy, {
+        if self.node_count() == 0 { return None; }
+        let (mut vec_stack, mut nearest): (Vec<StackLayer>, Option<(usize, D)>) = (vec![(0, VisitState::NotVisited)], None);
+
+        if let Some(start_idx) = start.filter(|idx| *idx < self.node_count() && !self.node_removed(*idx) && predicate(*idx)) {
+            trace.m_distance_evals += 1;
+            nearest = Some((start_idx, distance(start_idx)));
+            trace.log(SearchEvent::CANDIDATE { node: start_idx, dist: log_dist(distance(start_idx)) });
+        }
 
         // Called whenever the input point's distance must be compared to a node in the tree
-        let update_nearest = |mut v_stack: Vec<StackLayer>, mut near: Option<&'t Point<P>>, mut old_dist: f32, node_at: &'t CKDNode<P>| {
+        let update_nearest = |mut v_stack: Vec<StackLayer>, mut near: Option<(usize, D)>, node_idx: usize, trace: &mut QueryTrace| {
             v_stack.pop();
-            let dist = distance_func(&node_at.m_midpoint, point);
-            if dist < old_dist { (near, old_dist) = (Some(&*node_at.m_midpoint), dist); }
-            return (v_stack, near, old_dist)
+            if self.node_removed(node_idx) || !predicate(node_idx) { return (v_stack, near); }
+            trace.m_distance_evals += 1;
+            let dist: D = distance(node_idx);
+            if near.is_none_or(|(old_idx, old_dist)| dist < old_dist || (dist == old_dist && self.node_rank(node_idx) < self.node_rank(old_idx))) {
+                near = Some((node_idx, dist));
+                trace.log(SearchEvent::CANDIDATE { node: node_idx, dist: log_dist(dist) });
+            }
+            return (v_stack, near)
         };
 
         while vec_stack.len() > 0 {
             let last_idx: usize = vec_stack.len() - 1;
             let top: &mut (usize, VisitState) = &mut vec_stack[last_idx];
-            let cur_node: &CKDNode<P> = self.get(top.0);
+            let cur_idx: usize = top.0;
 
             match top.1 {
-                VisitState::Visited => { // visited case: first update the closest, then check for overlap w/ 
-                    (vec_stack, nearest, min_dist) = update_nearest(vec_stack, nearest, min_dist, &cur_node);
-                    if f32::abs(cur_node.get_axis_value() - point[cur_node.m_axis]) < min_dist {
-                        cur_node.travel_invert(point).and_then(|next_idx| { Some(vec_stack.push((next_idx, VisitState::NotVisited))) });
+                VisitState::Visited => { // visited case: first update the closest, then check for overlap w/
+                    trace.log(SearchEvent::BACKTRACK { node: cur_idx });
+                    (vec_stack, nearest) = update_nearest(vec_stack, nearest, cur_idx, trace);
+                    if let Some(far_idx) = self.node_travel_invert(cur_idx, point) {
+                        let bound: D = plane_distance(cur_idx);
+                        match nearest {
+                            Some((_, min_dist)) if !crosses(bound, min_dist) => {
+                                trace.m_pruned += 1;
+                                trace.log(SearchEvent::PRUNE { node: cur_idx, child: far_idx, bound: log_dist(bound), best: log_dist(min_dist) });
+                            },
+                            _ => {
+                                trace.log(SearchEvent::CROSS { node: cur_idx, child: far_idx });
+                                vec_stack.push((far_idx, VisitState::NotVisited));
+                            }
+                        }
                     }
                 },
 
                 VisitState::NotVisited => { // unvisited case: if at leaf we update closest, otherwise we keep moving down the tree
                     top.1 = VisitState::Visited;
-                    if cur_node.is_leaf() { (vec_stack, nearest, min_dist) = update_nearest(vec_stack, nearest, min_dist, &cur_node); }
-                    else { cur_node.travel(point).and_then(|next_idx| Some(vec_stack.push((next_idx, VisitState::NotVisited))) ); }
+                    trace.m_visited += 1;
+                    trace.log(SearchEvent::DESCEND { node: cur_idx });
+                    if self.node_is_leaf(cur_idx) { (vec_stack, nearest) = update_nearest(vec_stack, nearest, cur_idx, trace); }
+                    else { self.node_travel(cur_idx, point).and_then(|next_idx| Some(vec_stack.push((next_idx, VisitState::NotVisited))) ); }
                 }
             }
         }
+
         return nearest;
     }
+
+    /** The search behind `nearest_ties`: like `search_nearest_from`, but a subtree is only skipped once the lower bound on
+     its distance lies more than `tolerance` beyond the best distance so far, so that every point within `tolerance` of
+     the nearest one is found, whichever of them the search happens to reach first. Returns their node indices with
+     their distances, in no particular order. */
+    fn search_nearest_ties_from<S: KdScalar, Q: KdPoint<Scalar = S> + ?Sized, M: Metric<S>>(&self, point: &Q, start: Option<usize>, tolerance: S::Dist, metric: &M, predicate: impl Fn(usize) -> bool, trace: &mut QueryTrace) -> Vec<(usize, S::Dist)> where Self::Point: KdPoint<Scalar = S> {
+        let mut ties: Vec<(usize, S::Dist)> = Vec::new();
+        if self.node_count() == 0 { return ties; }
+        let mut best: Option<S::Dist> = None;
+        let within = |dist: S::Dist, best: Option<S::Dist>| best.is_none_or(|best| dist <= best + tolerance);
+
+        let seeded: Option<usize> = start.filter(|idx| *idx < self.node_count() && !self.node_removed(*idx) && predicate(*idx));
+        if let Some(start_idx) = seeded {
+            trace.m_distance_evals += 1;
+            let dist: S::Dist = metric.distance(self.node_point(start_idx), point, self.axis_count());
+            (best, ties) = (Some(dist), vec![(start_idx, dist)]);
+        }
+
+        // every node on the stack carries a lower bound on the distance to anything in its subtree
+        let mut vec_stack: Vec<(usize, S::Dist)> = vec![(0, S::Dist::zero())];
+        while let Some((cur_idx, bound)) = vec_stack.pop() {
+            if !within(bound, best) { trace.m_pruned += 1; continue; }
+            trace.m_visited += 1;
+
+            if seeded != Some(cur_idx) && !self.node_removed(cur_idx) && predicate(cur_idx) {
+                trace.m_distance_evals += 1;
+                let dist: S::Dist = metric.distance(self.node_point(cur_idx), point, self.axis_count());
+                if best.is_none_or(|best| dist < best) {
+                    best = Some(dist);
+                    ties.retain(|(_, tied)| within(*tied, best));
+                }
+
+                if within(dist, best) { ties.push((cur_idx, dist)); }
+            }
+
+            // push the far side first so the side the point lies on is searched first
+            let plane_dist: S::Dist = self.node_plane_distance(cur_idx, point, metric);
+            self.node_travel_invert(cur_idx, point).and_then(|far_idx| Some(vec_stack.push((far_idx, if plane_dist > bound { plane_dist } else { bound }))));
+            self.node_travel(cur_idx, point).and_then(|next_idx| Some(vec_stack.push((next_idx, bound))));
+        }
+
+        return ties;
+    }
+
+    /** The search behind `k_nearest`: candidates are kept in a bounded max-heap, so the worst of the current `k` is always on
+     top. Tallies the work done into `trace` and returns at most `k` node indices with their distances, closest first. */
+    fn search_k_nearest<S: KdScalar, Q: KdPoint<Scalar = S> + ?Sized, M: Metric<S>>(&self, point: &Q, k: usize, metric: &M, trace: &mut QueryTrace) -> Vec<(usize, S::Dist)> where Self::Point: KdPoint<Scalar = S> {
+        return self.search_k_nearest_by(
+            point, k,
+            |idx| metric.distance(self.node_point(idx), point, self.axis_count()),
+            |idx| self.node_plane_distance(idx, point, metric),
+            trace
+        );
+    }
+
+    /** The walk shared by the k-nearest searches, with distances measured as for `search_nearest_by`. */
+    fn search_k_nearest_by<Q, D>(&self, point: &Q, k: usize, distance: impl Fn(usize) -> D, plane_distance: impl Fn(usize) -> D, trace: &mut QueryTrace) -> Vec<(usize, D)>
+    where
+        Q: KdPoint<Scalar = <Self::Point as KdPoint>::Scalar> + ?Sized,
+        D: KdNumber, {
+        if self.node_count() == 0 || k == 0 { return Vec::new(); }
+        let (mut vec_stack, mut heap): (Vec<StackLayer>, BinaryHeap<NeighborEntry<D>>) = (vec![(0, VisitState::NotVisited)], BinaryHeap::with_capacity(k + 1));
+
+        // Called whenever the input point's distance must be compared to a node in the tree
+        let update_heap = |heap: &mut BinaryHeap<NeighborEntry<D>>, node_idx: usize, trace: &mut QueryTrace| {
+            if self.node_removed(node_idx) { return; }
+            trace.m_distance_evals += 1;
+            let dist: D = distance(node_idx);
+            if heap.len() < k { heap.push(NeighborEntry { m_dist: dist, m_idx: node_idx }); }
+            else if dist < heap.peek().unwrap().m_dist {
+                heap.pop();
+                heap.push(NeighborEntry { m_dist: dist, m_idx: node_idx });
+            }
+        };
+
+        // Until the heap is full every subtree could still hold one of the k nearest
+        let crosses = |heap: &BinaryHeap<NeighborEntry<D>>, bound: D| {
+            return heap.len() < k || bound < heap.peek().unwrap().m_dist;
+        };
+
+        while vec_stack.len() > 0 {
+            let last_idx: usize = vec_stack.len() - 1;
+            let top: &mut (usize, VisitState) = &mut vec_stack[last_idx];
+            let cur_idx: usize = top.0;
+
+            match top.1 {
+                VisitState::Visited => {
+                    vec_stack.pop();
+                    update_heap(&mut heap, cur_idx, trace);
+                    if let Some(far_idx) = self.node_travel_invert(cur_idx, point) {
+                        if crosses(&heap, plane_distance(cur_idx)) { vec_stack.push((far_idx, VisitState::NotVisited)); }
+                        else { trace.m_pruned += 1; }
+                    }
+                },
+
+                VisitState::NotVisited => {
+                    top.1 = VisitState::Visited;
+                    trace.m_visited += 1;
+                    if self.node_is_leaf(cur_idx) { vec_stack.pop(); update_heap(&mut heap, cur_idx, trace); }
+                    else { self.node_travel(cur_idx, point).and_then(|next_idx| Some(vec_stack.push((next_idx, VisitState::NotVisited))) ); }
+                }
+            }
+        }
+
+        return heap.into_sorted_vec().into_iter().map(|entry| (entry.m_idx, entry.m_dist)).collect();
+    }
+
+    /** The search behind `within_radius`: a subtree on the far side of a splitting plane is only explored if the plane itself
+     lies within `radius` of the input point. Returns the matching node indices with their distances, closest first. */
+    fn search_radius<Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&self, point: &Q, radius: f32, metric: &M) -> Vec<(usize, f32)> where Self::Point: KdPoint<Scalar = f32> {
+        let mut matches: Vec<(usize, f32)> = Vec::new();
+        if self.node_count() == 0 || radius < 0f32 { return matches; }
+        let mut vec_stack: Vec<usize> = vec![0];
+
+        while let Some(cur_idx) = vec_stack.pop() {
+            if !self.node_removed(cur_idx) {
+                let dist: f32 = metric.distance(self.node_point(cur_idx), point, self.axis_count());
+                if dist <= radius { matches.push((cur_idx, dist)); }
+            }
+
+            self.node_travel(cur_idx, point).and_then(|next_idx| Some(vec_stack.push(next_idx)));
+            if self.node_plane_distance(cur_idx, point, metric) <= radius {
+                self.node_travel_invert(cur_idx, point).and_then(|next_idx| Some(vec_stack.push(next_idx)));
+            }
+        }
+
+        matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+        return matches;
+    }
+
+    /** The search behind `range_search`: a node's left subtree (values less than or equal to its split) is only explored if the
+     box reaches down to the split value along the node's axis, and its right subtree only if the box extends above it.
+     Returns the matching node indices in traversal order. */
+    fn search_range(&self, bounds: &AxisBox<<Self::Point as KdPoint>::Scalar>) -> Vec<usize> where <Self::Point as KdPoint>::Scalar: KdScalar {
+        let mut matches: Vec<usize> = Vec::new();
+        if self.node_count() == 0 { return matches; }
+        let mut vec_stack: Vec<usize> = vec![0];
+
+        while let Some(cur_idx) = vec_stack.pop() {
+            if !self.node_removed(cur_idx) && bounds.contains(self.node_point(cur_idx)) { matches.push(cur_idx); }
+
+            let (axis, dirs): (usize, [Option<usize>; 2]) = (self.node_axis(cur_idx), self.node_dirs(cur_idx));
+            let split = self.node_point(cur_idx).coord(axis);
+            if bounds.m_upper[axis] > split { dirs[1].and_then(|next_idx| Some(vec_stack.push(next_idx))); }
+            if bounds.m_lower[axis] <= split { dirs[0].and_then(|next_idx| Some(vec_stack.push(next_idx))); }
+        }
+
+        return matches;
+    }
+
+    /** The euclidean distance from the node at `idx` to the segment from `a` to `b`, along with the parameter t in [0, 1]
+     of the segment's closest approach to it, a + t (b - a). */
+    fn node_segment_distance<Q: KdPoint<Scalar = f32> + ?Sized>(&self, idx: usize, a: &Q, b: &Q) -> (f32, f32) where Self::Point: KdPoint<Scalar = f32> {
+        let (point, k): (&Self::Point, usize) = (self.node_point(idx), self.axis_count());
+        let length_sq: f32 = (0..k).map(|axis| (b.coord(axis) - a.coord(axis)).powi(2)).sum();
+        let t: f32 = if length_sq == 0f32 { 0f32 } else {
+            ((0..k).map(|axis| (point.coord(axis) - a.coord(axis)) * (b.coord(axis) - a.coord(axis))).sum::<f32>() / length_sq).clamp(0f32, 1f32)
+        };
+
+        let dist: f32 = (0..k).map(|axis| (point.coord(axis) - (a.coord(axis) + t * (b.coord(axis) - a.coord(axis)))).powi(2)).sum::<f32>().sqrt();
+        return (dist, t);
+    }
+
+    /** The search behind `nearest_to_segment`. Every node on the stack carries the box of space its subtree covers, and a
+     subtree is skipped once the gap between that box and the bounding box of the segment is no smaller than the worst of the
+     current `k` best, since no point of the segment can come closer to the box than that. Nodes whose index `predicate`
+     rejects are never candidates. Returns at most `k` node indices with their distance and t, closest first. */
+    fn search_segment<Q: KdPoint<Scalar = f32> + ?Sized>(&self, a: &Q, b: &Q, k: usize, predicate: impl Fn(usize) -> bool) -> Vec<(usize, f32, f32)> where Self::Point: KdPoint<Scalar = f32> {
+        if self.node_count() == 0 || k == 0 { return Vec::new(); }
+        let dims: usize = self.axis_count();
+        let (seg_lower, seg_upper): (Vec<f32>, Vec<f32>) = ((0..dims).map(|axis| a.coord(axis).min(b.coord(axis))).collect(), (0..dims).map(|axis| a.coord(axis).max(b.coord(axis))).collect());
+
+        // lower bound on the distance from the segment to anything inside the cell [lower, upper]
+        let cell_distance = |lower: &[f32], upper: &[f32]| {
+            return (0..dims).map(|axis| (lower[axis] - seg_upper[axis]).max(seg_lower[axis] - upper[axis]).max(0f32).powi(2)).sum::<f32>().sqrt();
+        };
+
+        let mut heap: BinaryHeap<NeighborEntry> = BinaryHeap::with_capacity(k + 1);
+        let mut vec_stack: Vec<(usize, Vec<f32>, Vec<f32>)> = vec![(0, vec![f32::NEG_INFINITY; dims], vec![f32::INFINITY; dims])];
+
+        while let Some((cur_idx, lower, upper)) = vec_stack.pop() {
+            if heap.len() == k && cell_distance(&lower, &upper) >= heap.peek().unwrap().m_dist { continue; }
+
+            if !self.node_removed(cur_idx) && predicate(cur_idx) {
+                let dist: f32 = self.node_segment_distance(cur_idx, a, b).0;
+                if heap.len() < k { heap.push(NeighborEntry { m_dist: dist, m_idx: cur_idx }); }
+                else if dist < heap.peek().unwrap().m_dist {
+                    heap.pop();
+                    heap.push(NeighborEntry { m_dist: dist, m_idx: cur_idx });
+                }
+            }
+
+            // split the cell at this node's plane, then push the farther child first so the nearer one is searched first
+            let (axis, dirs): (usize, [Option<usize>; 2]) = (self.node_axis(cur_idx), self.node_dirs(cur_idx));
+            let split: f32 = self.node_point(cur_idx).coord(axis);
+            let (mut left_upper, mut right_lower): (Vec<f32>, Vec<f32>) = (upper.clone(), lower.clone());
+            (left_upper[axis], right_lower[axis]) = (split, split);
+
+            let mut children: Vec<(f32, usize, Vec<f32>, Vec<f32>)> = Vec::with_capacity(2);
+            if let Some(left_idx) = dirs[0] { children.push((cell_distance(&lower, &left_upper), left_idx, lower, left_upper)); }
+            if let Some(right_idx) = dirs[1] { children.push((cell_distance(&right_lower, &upper), right_idx, right_lower, upper)); }
+            children.sort_by(|c1, c2| c2.0.total_cmp(&c1.0));
+            for (_, child_idx, child_lower, child_upper) in children { vec_stack.push((child_idx, child_lower, child_upper)); }
+        }
+
+        return heap.into_sorted_vec().into_iter().map(|entry| {
+            let (dist, t) = self.node_segment_distance(entry.m_idx, a, b);
+            (entry.m_idx, dist, t)
+        }).collect();
+    }
+
+    /** The exporter behind `export_dot`: one box per node, showing `label` of the node along with its split axis (named by
+     `axis_names`) and split value, dashed if the node was removed, and one edge per child link, marked "<=" towards the
+     left child and ">" towards the right one. */
+    fn write_dot(&self, axis_names: &[&str], label: impl Fn(usize) -> String) -> String where <Self::Point as KdPoint>::Scalar: Display {
+        let mut dot: String = String::from("digraph kdtree {\n    node [shape=box];\n");
+        for idx in 0..self.node_count() {
+            let axis: usize = self.node_axis(idx);
+            let text: String = format!("{}\n{} = {}", label(idx), axis_name(axis_names, axis), self.node_point(idx).coord(axis));
+            dot += &format!("    n{} [label={}{}];\n", idx, dot_string(&text), if self.node_removed(idx) { ", style=dashed" } else { "" });
+            for (dir, child_idx) in self.node_dirs(idx).iter().enumerate() {
+                if let Some(child_idx) = child_idx { dot += &format!("    n{} -> n{} [label=\"{}\"];\n", idx, child_idx, ["<=", ">"][dir]); }
+            }
+        }
+
+        return dot + "}\n";
+    }
+
+    /** The exporter behind `export_json`: the subtree rooted at `idx` as a nested JSON object, or null if there is none. */
+    fn write_json(&self, idx: Option<usize>, axis_names: &[&str], label: &impl Fn(usize) -> String) -> String where <Self::Point as KdPoint>::Scalar: Display {
+        let Some(idx) = idx.filter(|idx| *idx < self.node_count()) else { return String::from("null"); };
+        let axis: usize = self.node_axis(idx);
+        let [left_idx, right_idx] = self.node_dirs(idx);
+        return format!(
+            "{{\"label\":{},\"axis\":{},\"split\":{},\"removed\":{},\"left\":{},\"right\":{}}}",
+            json_string(&label(idx)), json_string(&axis_name(axis_names, axis)), self.node_point(idx).coord(axis), self.node_removed(idx),
+            self.write_json(left_idx, axis_names, label), self.write_json(right_idx, axis_names, label)
+        );
+    }
+
+    /** The search behind `farthest_neighbor`. Every node on the stack carries its cell, the box of space its subtree covers
+     clipped to `bounds`, which must enclose every point of the tree. The distance to the corner of a cell farthest from
+     the input point bounds the distance to anything inside the cell from above, provided `metric` grows with the
+     difference along every axis (every metric but `Angular`), so a subtree is skipped once that bound is no greater than
+     the best distance so far. Nodes whose index `predicate` rejects are never candidates. Tallies the work done into
+     `trace` and returns the farthest node index with its distance. */
+    fn search_farthest<Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&self, point: &Q, bounds: &AxisBox, metric: &M, predicate: impl Fn(usize) -> bool, trace: &mut QueryTrace) -> Option<(usize, f32)> where Self::Point: KdPoint<Scalar = f32> {
+        if self.node_count() == 0 { return None; }
+        let dims: usize = self.axis_count();
+        let query: Vec<f32> = (0..dims).map(|axis| point.coord(axis)).collect();
+
+        // upper bound on the distance from the input point to anything inside the cell [lower, upper]
+        let cell_distance = |lower: &[f32], upper: &[f32]| {
+            let corner: Vec<f32> = (0..dims).map(|axis| if query[axis] - lower[axis] >= upper[axis] - query[axis] { lower[axis] } else { upper[axis] }).collect();
+            return metric.distance(&corner, &query, dims);
+        };
+
+        let mut farthest: Option<(usize, f32)> = None;
+        let mut vec_stack: Vec<(usize, Vec<f32>, Vec<f32>)> = vec![(0, bounds.m_lower.clone(), bounds.m_upper.clone())];
+
+        while let Some((cur_idx, lower, upper)) = vec_stack.pop() {
+            if farthest.is_some_and(|(_, best)| cell_distance(&lower, &upper) <= best) { trace.m_pruned += 1; continue; }
+            trace.m_visited += 1;
+
+            if !self.node_removed(cur_idx) && predicate(cur_idx) {
+                trace.m_distance_evals += 1;
+                let dist: f32 = metric.distance(self.node_point(cur_idx), point, dims);
+                if farthest.is_none_or(|(_, best)| dist > best) { farthest = Some((cur_idx, dist)); }
+            }
+
+            // split the cell at this node's plane, then push the child with the nearer bound first so the farther one is searched first
+            let (axis, dirs): (usize, [Option<usize>; 2]) = (self.node_axis(cur_idx), self.node_dirs(cur_idx));
+            let split: f32 = self.node_point(cur_idx).coord(axis);
+            let (mut left_upper, mut right_lower): (Vec<f32>, Vec<f32>) = (upper.clone(), lower.clone());
+            (left_upper[axis], right_lower[axis]) = (split.min(upper[axis]), split.max(lower[axis]));
+
+            let mut children: Vec<(f32, usize, Vec<f32>, Vec<f32>)> = Vec::with_capacity(2);
+            if let Some(left_idx) = dirs[0] { children.push((cell_distance(&lower, &left_upper), left_idx, lower, left_upper)); }
+            if let Some(right_idx) = dirs[1] { children.push((cell_distance(&right_lower, &upper), right_idx, right_lower, upper)); }
+            children.sort_by(|c1, c2| c1.0.total_cmp(&c2.0));
+            for (_, child_idx, child_lower, child_upper) in children { vec_stack.push((child_idx, child_lower, child_upper)); }
+        }
+
+        return farthest;
+    }
+}
+
+/** A search distance as logged in a `SearchEvent`, whatever the units it was measured in. */
+fn log_dist<D: ToPrimitive>(dist: D) -> f32 {
+    return dist.to_f32().unwrap_or(f32::NAN);
+}
+
+/** The name `axis_names` gives `axis`, or the number of the axis if it gives none. */
+fn axis_name(axis_names: &[&str], axis: usize) -> String {
+    return axis_names.get(axis).map_or(axis.to_string(), |name| name.to_string());
+}
+
+/** Quotes `text` as a JSON string: quotes and backslashes are escaped, control characters written as \u00XX escapes, and
+ everything else (non-ASCII included) passed through as is. */
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted: String = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => { quoted += "\\\""; },
+            '\\' => { quoted += "\\\\"; },
+            c if c.is_control() => { quoted += &format!("\\u{:04x}", c as u32); },
+            c => { quoted.push(c); }
+        };
+    }
+
+    return quoted + "\"";
+}
+
+/** Quotes `text` as a DOT string: quotes and backslashes are escaped, line breaks become DOT's own \n escape and any
+ other control characters are left out. Everything else (non-ASCII included) is passed through as is. */
+fn dot_string(text: &str) -> String {
+    let mut quoted: String = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => { quoted += "\\\""; },
+            '\\' => { quoted += "\\\\"; },
+            '\n' => { quoted += "\\n"; },
+            c if c.is_control() => (),
+            c => { quoted.push(c); }
+        };
+    }
+
+    return quoted + "\"";
+}
+
+/** Checks that the child links of a node list laid out as by `raw_nodes` form a single tree rooted at index 0 in which every
+ child comes after its parent, and that every split axis is below `dimensions`. */
+fn check_raw_links(dimensions: usize, links: impl ExactSizeIterator<Item = (usize, [Option<usize>; 2])>) -> Result<(), &'static str> {
+    let mut referenced: Vec<bool> = vec![false; links.len()];
+    let node_count: usize = links.len();
+    for (idx, (axis, dirs)) in links.enumerate() {
+        if axis >= dimensions { return Err("node split axis out of range"); }
+        for child_idx in dirs.iter().flatten() {
+            if *child_idx <= idx || *child_idx >= node_count { return Err("node child index out of order"); }
+            if mem::replace(&mut referenced[*child_idx], true) { return Err("node has more than one parent"); }
+        }
+    }
+
+    if referenced.iter().skip(1).any(|r| !r) { return Err("node is unreachable from the root"); }
+    return Ok(());
+}
+
+impl<P: KdPoint> NodeList for CKDTree<P> {
+    type Point = P;
+
+    fn node_count(&self) -> usize {
+        return self.m_nodelist.len();
+    }
+
+    fn axis_count(&self) -> usize {
+        return self.m_k;
+    }
+
+    fn node_axis(&self, idx: usize) -> usize {
+        return self.m_nodelist[idx].m_axis;
+    }
+
+    fn node_dirs(&self, idx: usize) -> [Option<usize>; 2] {
+        return self.m_nodelist[idx].m_dirs;
+    }
+
+    fn node_removed(&self, idx: usize) -> bool {
+        return self.m_nodelist[idx].m_removed;
+    }
+
+    fn node_point(&self, idx: usize) -> &Self::Point {
+        return &self.m_nodelist[idx].m_midpoint;
+    }
+}
+
+impl<P: KdPoint<Scalar = f32>> CKDTree<P> {
+
+    /** Walks every node of this tree, removed ones included, to summarise its shape. */
+    pub fn stats(&self) -> TreeStats {
+        return self.shape_stats();
+    }
+
+    /** Find an approximate nearest neighbor to the input point under the distance `metric`. A subtree on the far side of a
+     splitting plane is skipped if the plane lies farther than `min_dist / (1 + eps)`, so the returned point is at most
+     `(1 + eps)` times farther away than the true nearest neighbor (for metrics whose distances scale linearly). With an
+     `eps` of 0 the search is exact and matches `nearest_neighbor`, which makes it the baseline for the visited node count. */
+    pub fn approx_nearest<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, eps: f32, metric: &M) -> Option<ApproxNeighbor<'t, P>> {
+        assert!(eps >= 0f32, "CKDTree::approx_nearest eps must be greater than or equal to 0.0, you entered {}", eps);
+        let mut trace: QueryTrace = QueryTrace::default();
+        return self.search_nearest_from(point, None, eps, metric, |_| true, &mut trace)
+            .map(|(idx, dist)| ApproxNeighbor { m_point: self.node_point(idx), m_dist: dist, m_visited: trace.m_visited, m_node: idx });
+    }
+
+    /** Find the nearest neighbor to the input point under `metric`, warm started from `hint`, the answer of an earlier query
+     (typically for a nearby point, such as the previous sample along a path). The hinted point's distance bounds the
+     search from the outset, so the closer it is to the new answer the fewer nodes need visiting. The answer is the point
+     `nearest_neighbor` returns, ties included, since ties resolve by `node_rank` wherever the search starts. The visited
+     count tells how much the hint saved. */
+    pub fn nearest_neighbor_warm<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, hint: Option<NearestHint>) -> Option<ApproxNeighbor<'t, P>> {
+        let mut trace: QueryTrace = QueryTrace::default();
+        return self.search_nearest_from(point, hint.map(|h| h.0), 0f32, metric, |_| true, &mut trace)
+            .map(|(idx, dist)| ApproxNeighbor { m_point: self.node_point(idx), m_dist: dist, m_visited: trace.m_visited, m_node: idx });
+    }
+
+    /** Same as `nearest_neighbor`, but also returns a `QueryTrace` of the work the search did. */
+    pub fn nearest_neighbor_traced<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M) -> (Option<(&'t P, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::default();
+        let found = self.search_nearest(point, metric, |_| true, &mut trace).map(|(idx, dist)| (self.node_point(idx), dist));
+        return (found, trace);
+    }
+
+    /** Same as `nearest_neighbor_traced`, but the trace also logs every step of the search, in order, so it can be replayed:
+     the descent from the root towards the input point (the path `tree_travel_iter` walks), each node backtracked to,
+     every far side crossed into or pruned, and every update of the nearest point found so far. */
+    pub fn nearest_neighbor_logged<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M) -> (Option<(&'t P, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::logging();
+        let found = self.search_nearest(point, metric, |_| true, &mut trace).map(|(idx, dist)| (self.node_point(idx), dist));
+        return (found, trace);
+    }
+
+    /** Find the nearest neighbor to the input point under the distance `metric` among only the points for which `predicate`
+     returns true. Rejected points are passed over exactly like removed ones: they are never candidates, but the nodes
+     holding them still split space, so pruning stays correct. Returns the point along with its distance from the input point. */
+    pub fn nearest_neighbor_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, predicate: impl Fn(&P) -> bool) -> Option<(&'t P, f32)> {
+        return self.search_nearest(point, metric, |idx| predicate(self.node_point(idx)), &mut QueryTrace::default())
+            .map(|(idx, dist)| (self.node_point(idx), dist));
+    }
+
+    /** Answers `nearest_neighbor` for every point in `points`, splitting the queries into contiguous chunks spread over
+     `threads` scoped worker threads that all share this (immutable) tree. Results come back in the same order as `points`. */
+    pub fn nearest_batch<'t, Q, M>(&'t self, points: &[Q], metric: &M, threads: usize) -> Vec<Option<(&'t P, f32)>>
+    where
+        P: Sync,
+        Q: KdPoint<Scalar = f32> + Sync,
+        M: Metric + Sync, {
+        return run_batch(points, threads, |point: &Q| self.nearest_neighbor(point, metric));
+    }
+
+    /** Same as `k_nearest`, but also returns a `QueryTrace` of the work the search did. */
+    pub fn k_nearest_traced<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, k: usize, metric: &M) -> (Vec<(&'t P, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::default();
+        let found = self.search_k_nearest(point, k, metric, &mut trace).into_iter().map(|(idx, dist)| (self.node_point(idx), dist)).collect();
+        return (found, trace);
+    }
+
+    /** Find every point stored in this `CKDTree` whose distance to the input point is at most `radius` under the distance
+     `metric`. A subtree on the far side of a splitting plane is only explored if the plane itself
+     lies within `radius` of the input point. Returns the matching points paired with their distances, sorted from closest to farthest. */
+    pub fn within_radius<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, radius: f32, metric: &M) -> Vec<(&'t P, f32)> {
+        return self.search_radius(point, radius, metric).into_iter().map(|(idx, dist)| (self.node_point(idx), dist)).collect();
+    }
+
+    /** Writes the structure of this tree as a Graphviz DOT digraph. Every node is drawn as a box holding `label` of its
+     point along with its split axis, named by `axis_names` (or numbered where that runs out), and split value; removed
+     nodes are dashed. Edges to left children are marked "<=" and edges to right children ">". */
+    pub fn export_dot(&self, axis_names: &[&str], label: impl Fn(&P) -> String) -> String {
+        return self.write_dot(axis_names, |idx| label(self.node_point(idx)));
+    }
+
+    /** Writes the structure of this tree as nested JSON objects, one per node, of the form
+     {"label":..,"axis":..,"split":..,"removed":..,"left":..,"right":..}, where an absent child (or an empty tree) is null.
+     Labels and axis names are given as for `export_dot`. */
+    pub fn export_json(&self, axis_names: &[&str], label: impl Fn(&P) -> String) -> String {
+        return self.write_json(Some(0), axis_names, &|idx| label(self.node_point(idx)));
+    }
+
+    /** Find the point stored in this `CKDTree` farthest from the input point under the distance `metric`, which must grow
+     with the difference along every axis (every metric but `Angular` does). The search mirrors `nearest_neighbor`: each
+     subtree is bounded from above by the farthest corner of the space it covers, and skipped once that bound is no
+     greater than the farthest distance so far. `bounds` must enclose every point of the tree; the box from
+     `bounding_box()` can be kept and reused for as long as the tree does not change. Returns the point along with its
+     distance from the input point. */
+    pub fn farthest_neighbor<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, bounds: &AxisBox) -> Option<(&'t P, f32)> {
+        return self.farthest_neighbor_filtered(point, metric, bounds, |_| true);
+    }
+
+    /** Same as `farthest_neighbor`, but only points for which `predicate` returns true are candidates. */
+    pub fn farthest_neighbor_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &Q, metric: &M, bounds: &AxisBox, predicate: impl Fn(&P) -> bool) -> Option<(&'t P, f32)> {
+        return self.search_farthest(point, bounds, metric, |idx| predicate(self.node_point(idx)), &mut QueryTrace::default()).map(|(idx, dist)| (self.node_point(idx), dist));
+    }
+
+    /** Returns a `NearestIter` over every point of this tree in increasing distance from `point` under `metric`. */
+    pub fn nearest_iter<'t, Q: KdPoint<Scalar = f32> + ?Sized, M: Metric>(&'t self, point: &'t Q, metric: &'t M) -> NearestIter<'t, Self, Q, M> {
+        return NearestIter::new(self, point, metric);
+    }
+
+    /** Find the `k` points closest (in euclidean distance) to the line segment from `a` to `b`, rather than to a single
+     point. Each point comes with its distance to the segment and the parameter t in [0, 1] of the segment's closest
+     approach to it, a + t (b - a). Subtrees are pruned by how far their region of space lies from the segment's
+     bounding box. Sorted from closest to farthest. */
+    pub fn nearest_to_segment<'t, Q: KdPoint<Scalar = f32> + ?Sized>(&'t self, a: &Q, b: &Q, k: usize) -> Vec<(&'t P, f32, f32)> {
+        return self.nearest_to_segment_filtered(a, b, k, |_| true);
+    }
+
+    /** Same as `nearest_to_segment`, but only points for which `predicate` returns true are candidates. */
+    pub fn nearest_to_segment_filtered<'t, Q: KdPoint<Scalar = f32> + ?Sized>(&'t self, a: &Q, b: &Q, k: usize, predicate: impl Fn(&P) -> bool) -> Vec<(&'t P, f32, f32)> {
+        return self.search_segment(a, b, k, |idx| predicate(self.node_point(idx))).into_iter().map(|(idx, dist, t)| (self.node_point(idx), dist, t)).collect();
+    }
+}
+
+/** The queries a `CKDTree` answers over any `KdScalar` coordinates, not just f32: integer trees (such as FEH stats kept as
+ `u8` or `i16`) measure distances exactly in integers under the metrics that support them, so points at the same
+ distance compare equal instead of being told apart by float rounding. */
+impl<P: KdPoint> CKDTree<P> where P::Scalar: KdScalar {
+
+    /** Find the nearest neighbor to the input point among the points stored in this `CKDTree` under the distance `metric`.
+     Of several points at exactly the same distance, the one earliest in the node list is kept. Returns the nearest
+     point along with its distance from the input point. */
+    pub fn nearest_neighbor<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, metric: &M) -> Option<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        return self.search_nearest(point, metric, |_| true, &mut QueryTrace::default()).map(|(idx, dist)| (self.node_point(idx), dist));
+    }
+
+    /** Find the `k` nearest neighbors to the input point among the points stored in this `CKDTree` under the distance
+     `metric`. Candidates are kept in a bounded max-heap, so the worst of the current `k` is always on top.
+     Returns at most `k` points paired with their distances, sorted from closest to farthest. */
+    pub fn k_nearest<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, k: usize, metric: &M) -> Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        return self.search_k_nearest(point, k, metric, &mut QueryTrace::default()).into_iter().map(|(idx, dist)| (self.node_point(idx), dist)).collect();
+    }
+
+    /** Find every point whose distance to the input point lies within `tolerance` of the nearest neighbor's under the
+     distance `metric`. Where `nearest_neighbor` keeps only the tied point earliest in the node list, this returns all
+     of them in node list order (the order they were pushed in, for a tree built by pushing), the same rule
+     `KdTree::nearest_ties` orders payloads by, so the order does not depend on the path the search took. Over integer
+     coordinates a `tolerance` of 0 finds the points at exactly the nearest distance. Returns the points paired with
+     their distances from the input point. */
+    pub fn nearest_ties<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, tolerance: <P::Scalar as KdScalar>::Dist, metric: &M) -> Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        return self.nearest_ties_filtered(point, tolerance, metric, |_| true);
+    }
+
+    /** Same as `nearest_ties`, but only points for which `predicate` returns true are candidates. */
+    pub fn nearest_ties_filtered<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, tolerance: <P::Scalar as KdScalar>::Dist, metric: &M, predicate: impl Fn(&P) -> bool) -> Vec<(&'t P, <P::Scalar as KdScalar>::Dist)> {
+        return self.nearest_ties_filtered_traced(point, tolerance, metric, predicate).0;
+    }
+
+    /** Same as `nearest_ties_filtered`, but also returns a `QueryTrace` of the work the search did. */
+    pub fn nearest_ties_filtered_traced<'t, Q: KdPoint<Scalar = P::Scalar> + ?Sized, M: Metric<P::Scalar>>(&'t self, point: &Q, tolerance: <P::Scalar as KdScalar>::Dist, metric: &M, predicate: impl Fn(&P) -> bool) -> (Vec<(&'t P, <P::Scalar as KdScalar>::Dist)>, QueryTrace) {
+        assert!(tolerance >= Zero::zero(), "CKDTree::nearest_ties tolerance must be greater than or equal to 0, you entered {}", tolerance);
+        let mut trace: QueryTrace = QueryTrace::default();
+        let mut ties: Vec<(usize, <P::Scalar as KdScalar>::Dist)> = self.search_nearest_ties_from(point, None, tolerance, metric, |idx| predicate(self.node_point(idx)), &mut trace);
+        ties.sort_by_key(|(idx, _)| self.node_rank(*idx));
+
+        return (ties.into_iter().map(|(idx, dist)| (self.node_point(idx), dist)).collect(), trace);
+    }
+
+    /** Find every point stored in this `CKDTree` that lies inside the axis-aligned box `bounds`. A node's left subtree
+     (values less than or equal to its split) is only explored if the box reaches down to the split value along the
+     node's axis, and its right subtree only if the box extends above it. Points are returned in traversal order. */
+    pub fn range_search<'t>(&'t self, bounds: &AxisBox<P::Scalar>) -> Vec<&'t P> {
+        return self.search_range(bounds).into_iter().map(|idx| self.node_point(idx)).collect();
+    }
+
+    /** Returns the smallest box enclosing every point of this tree that has not been removed. */
+    pub fn bounding_box(&self) -> AxisBox<P::Scalar> {
+        let mut bounds: AxisBox<P::Scalar> = AxisBox::empty(self.m_k);
+        for node in self.m_nodelist.iter().filter(|node| !node.m_removed) { bounds.enclose(&node.m_midpoint); }
+        return bounds;
+    }
+}
+
+impl<P: KdPoint<Scalar = f32>> SpatialIndex for CKDTree<P> {
+    type Point = P;
+
+    fn index_dimensions(&self) -> usize {
+        return self.m_k;
+    }
+
+    fn point_of(&self, id: usize) -> &Self::Point {
+        return self.node_point(id);
+    }
+
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize> {
+        return self.search_range(bounds);
+    }
+}
+
+impl<P: KdPoint<Scalar = f32>, M: Metric> MetricIndex<M> for CKDTree<P> {
+    fn query_nearest(&self, point: &Self::Point, metric: &M) -> Option<(usize, f32)> {
+        return self.search_nearest(point, metric, |_| true, &mut QueryTrace::default());
+    }
+
+    fn query_k_nearest(&self, point: &Self::Point, k: usize, metric: &M) -> Vec<(usize, f32)> {
+        return self.search_k_nearest(point, k, metric, &mut QueryTrace::default());
+    }
+
+    fn query_radius(&self, point: &Self::Point, radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        return self.search_radius(point, radius, metric);
+    }
+}
+
+/** Runs `query` on every point in `points`, splitting them into contiguous chunks spread over `threads` scoped worker
+ threads. Results come back in the same order as `points`. */
+pub(crate) fn run_batch<Q: Sync, R: Send>(points: &[Q], threads: usize, query: impl Fn(&Q) -> R + Sync) -> Vec<R> {
+    if points.len() == 0 { return Vec::new(); }
+    let chunk_size: usize = points.len().div_ceil(usize::max(threads, 1));
+    let query = &query;
+
+    return thread::scope(|scope| {
+        let workers: Vec<thread::ScopedJoinHandle<Vec<R>>> = points.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(query).collect()))
+            .collect();
+
+        return workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect();
+    });
+}
+
+/** A point of a `KdTree` on its way through a median build: its coordinates along with its payload index. A `KdPoint`
+ through its coordinates, so the build is the very same one `CKDTree::from_median` does. */
+struct TaggedPoint<const K: usize>([f32; K], usize);
+impl<const K: usize> KdPoint for TaggedPoint<K> {
+    type Scalar = f32;
+
+    fn dimensions(&self) -> usize {
+        return K;
+    }
+
+    fn coord(&self, axis: usize) -> f32 {
+        return self.0[axis];
+    }
+}
+
+/** A KD-tree over points with exactly `K` axes. Unlike `CKDTree`, which keeps whole points (often pointers to them) in its nodes, a `KdTree`
+ stores coordinates inline as `[f32; K]` in a struct-of-arrays layout next to the split axes, child links and tombstones,
+ so a search never leaves these arrays. What a point stands for is kept out of the tree: each point carries a payload
+ index into a table owned by the caller, and queries answer with those indices. */
+#[derive(Debug, Clone)]
+pub struct KdTree<const K: usize> {
+    m_coords: Vec<[f32; K]>,
+    m_axes: Vec<usize>,
+    m_dirs: Vec<[Option<usize>; 2]>,
+    m_removed: Vec<bool>,
+    m_payloads: Vec<usize>,
+    m_bounds: AxisBox, // encloses every point ever pushed, so it may outgrow the live points until a rebuild
+    m_split_rule: SplitRule,
+    m_removed_count: usize,
+    m_rebuild_ratio: f32 // fraction of removed nodes at which the tree is rebuilt
+}
+
+impl<const K: usize> NodeList for KdTree<K> {
+    type Point = [f32; K];
+
+    fn node_count(&self) -> usize {
+        return self.m_coords.len();
+    }
+
+    fn axis_count(&self) -> usize {
+        return K;
+    }
+
+    fn node_axis(&self, idx: usize) -> usize {
+        return self.m_axes[idx];
+    }
+
+    fn node_dirs(&self, idx: usize) -> [Option<usize>; 2] {
+        return self.m_dirs[idx];
+    }
+
+    fn node_removed(&self, idx: usize) -> bool {
+        return self.m_removed[idx];
+    }
+
+    fn node_point(&self, idx: usize) -> &Self::Point {
+        return &self.m_coords[idx];
+    }
+
+    fn node_rank(&self, idx: usize) -> usize {
+        return self.m_payloads[idx];
+    }
+}
+
+impl<const K: usize> KdTree<K> {
+    /** Creates a new, empty KdTree */
+    pub fn new() -> Self {
+        return KdTree {
+            m_coords: Vec::new(),
+            m_axes: Vec::new(),
+            m_dirs: Vec::new(),
+            m_removed: Vec::new(),
+            m_payloads: Vec::new(),
+            m_bounds: AxisBox::empty(K),
+            m_split_rule: SplitRule::CYCLE_AXES,
+            m_removed_count: 0,
+            m_rebuild_ratio: 0.25f32
+        };
+    }
+
+    /** Builds a balanced `KdTree` from a whole point set at once, exactly like `CKDTree::from_median`. The payload index of
+     each point is its position in `points`. */
+    pub fn from_median(points: &[[f32; K]], rule: SplitRule) -> Self {
+        return Self::from_tagged(points.iter().enumerate().map(|(payload, coords)| TaggedPoint(*coords, payload)).collect(), rule);
+    }
+
+    /** Median builds `points` with the `CKDTree` builder, then lays its nodes out in struct-of-arrays form. */
+    fn from_tagged(points: Vec<TaggedPoint<K>>, rule: SplitRule) -> Self {
+        let built: CKDTree<TaggedPoint<K>> = CKDTree::from_median(K, points, rule);
+        let mut tree: KdTree<K> = KdTree::new();
+        tree.m_split_rule = rule;
+        for node in built.m_nodelist { tree.push_node(node.m_axis, node.m_dirs, false, node.m_midpoint.0, node.m_midpoint.1); }
+        return tree;
+    }
+
+    /** Appends a node to the end of every array. */
+    fn push_node(&mut self, axis: usize, dirs: [Option<usize>; 2], removed: bool, coords: [f32; K], payload: usize) {
+        self.m_axes.push(axis);
+        self.m_dirs.push(dirs);
+        self.m_removed.push(removed);
+        self.m_bounds.enclose(&coords);
+        self.m_coords.push(coords);
+        self.m_payloads.push(payload);
+    }
+
+    /** Inserts a point with the given payload index at the end of its travel path, as `CKDTree::push` does. */
+    pub fn push(&mut self, coords: [f32; K], payload: usize) -> &mut Self {
+        if self.m_coords.len() == 0 {
+            self.push_node(0, [None, None], false, coords, payload);
+            return self;
+        }
+
+        let mut prev_idx: usize = 0;
+        while let Some(next_idx) = self.node_travel(prev_idx, &coords) { prev_idx = next_idx; }
+
+        let new_idx: usize = self.m_coords.len();
+        let dir: usize = (coords[self.m_axes[prev_idx]] > self.m_coords[prev_idx][self.m_axes[prev_idx]]) as usize;
+        self.m_dirs[prev_idx][dir] = Some(new_idx);
+        self.push_node((self.m_axes[prev_idx] + 1) % K, [None, None], false, coords, payload);
+        return self;
+    }
+
+    /** Returns the number of points in this KdTree, not counting removed ones */
+    pub fn size(&self) -> usize {
+        return self.m_coords.len() - self.m_removed_count;
+    }
+
+    /** Returns the number of removed nodes still occupying space in this KdTree */
+    pub fn removed_count(&self) -> usize {
+        return self.m_removed_count;
+    }
+
+    /** Sets the fraction of removed nodes (in the range (0, 1]) at which `remove` rebuilds the tree. */
+    pub fn set_rebuild_ratio(&mut self, ratio: f32) -> &mut Self {
+        assert!(ratio > 0f32 && ratio <= 1f32, "KdTree::rebuild_ratio must be within the range (0, 1], you entered {}", ratio);
+        self.m_rebuild_ratio = ratio;
+        return self;
+    }
+
+    /** Returns the number of axes of the points in this KdTree, which is always `K` */
+    pub fn dimensions(&self) -> usize {
+        return K;
+    }
+
+    /** Returns the rule used to pick split axes whenever this tree is (re)built from median splits. */
+    pub fn split_rule(&self) -> SplitRule {
+        return self.m_split_rule;
+    }
+
+    /** Returns the number of nodes on the longest path from the root to a leaf (0 for an empty tree). */
+    pub fn depth(&self) -> usize {
+        return self.shape_stats().depth();
+    }
+
+    /** Walks every node of this tree, removed ones included, to summarise its shape. */
+    pub fn stats(&self) -> TreeStats {
+        return self.shape_stats();
+    }
+
+    /** Lays bare every node of this tree in node list order as (split axis, [left index, right index], removed, coordinates,
+     payload index). Feeding the nodes back to `from_raw_nodes` restores the exact same tree. */
+    pub fn raw_nodes(&self) -> impl Iterator<Item = (usize, [Option<usize>; 2], bool, &[f32; K], usize)> {
+        return (0..self.m_coords.len()).map(|idx| (self.m_axes[idx], self.m_dirs[idx], self.m_removed[idx], &self.m_coords[idx], self.m_payloads[idx]));
+    }
+
+    /** Reassembles a tree from nodes laid out as by `raw_nodes`, without re-inserting any point. Fails unless the nodes form
+     a single tree rooted at index 0 in which every child comes after its parent in the list. */
+    pub fn from_raw_nodes(rule: SplitRule, nodes: Vec<(usize, [Option<usize>; 2], bool, [f32; K], usize)>) -> Result<Self, &'static str> {
+        check_raw_links(K, nodes.iter().map(|(axis, dirs, _, _, _)| (*axis, *dirs)))?;
+
+        let mut tree: KdTree<K> = KdTree::new();
+        tree.m_split_rule = rule;
+        for (axis, dirs, removed, coords, payload) in nodes {
+            tree.push_node(axis, dirs, removed, coords, payload);
+            tree.m_removed_count += removed as usize;
+        }
+
+        return Ok(tree);
+    }
+
+    /** Rebuilds this tree from median splits using the split rule it was constructed with. Removed nodes are dropped. */
+    pub fn rebalance(&mut self) -> &mut Self {
+        let live: Vec<TaggedPoint<K>> = (0..self.m_coords.len()).filter(|idx| !self.m_removed[*idx])
+            .map(|idx| TaggedPoint(self.m_coords[idx], self.m_payloads[idx]))
+            .collect();
+
+        let rebuild_ratio: f32 = self.m_rebuild_ratio;
+        *self = Self::from_tagged(live, self.m_split_rule);
+        self.m_rebuild_ratio = rebuild_ratio;
+        return self;
+    }
+
+    /** Removes a point equal to `coords` (along every axis) from this tree, tombstoning it as `CKDTree::remove` does.
+     Returns the payload index of the removed point, or None if no matching point was found. */
+    pub fn remove(&mut self, coords: &[f32; K]) -> Option<usize> {
+        let mut next_idx: Option<usize> = self.m_coords.get(0).and(Some(0));
+        while let Some(cur_idx) = next_idx {
+            if !self.m_removed[cur_idx] && self.m_coords[cur_idx] == *coords {
+                let payload: usize = self.m_payloads[cur_idx];
+                self.m_removed[cur_idx] = true;
+                self.m_removed_count += 1;
+                if self.m_removed_count as f32 >= self.m_rebuild_ratio * self.m_coords.len() as f32 { self.rebalance(); }
+                return Some(payload);
+            }
+
+            next_idx = self.node_travel(cur_idx, coords);
+        }
+
+        return None;
+    }
+
+    /** Returns the payload index of the point held by node `id`, as named by this tree's `SpatialIndex` queries. */
+    pub fn payload_of(&self, id: usize) -> usize {
+        return self.m_payloads[id];
+    }
+
+    /** Find the nearest neighbor to `point` under the distance `metric`. Returns its payload index along with its distance. */
+    pub fn nearest_neighbor<M: Metric>(&self, point: &[f32; K], metric: &M) -> Option<(usize, f32)> {
+        return self.nearest_neighbor_traced(point, metric).0;
+    }
+
+    /** Find an approximate nearest neighbor to `point`, as `CKDTree::approx_nearest` does. The point is reported by its payload index. */
+    pub fn approx_nearest<'t, M: Metric>(&'t self, point: &[f32; K], eps: f32, metric: &M) -> Option<ApproxNeighbor<'t, usize>> {
+        assert!(eps >= 0f32, "KdTree::approx_nearest eps must be greater than or equal to 0.0, you entered {}", eps);
+        let mut trace: QueryTrace = QueryTrace::default();
+        return self.search_nearest_from(point, None, eps, metric, |_| true, &mut trace)
+            .map(|(idx, dist)| ApproxNeighbor { m_point: &self.m_payloads[idx], m_dist: dist, m_visited: trace.m_visited, m_node: idx });
+    }
+
+    /** Find the nearest neighbor to `point`, warm started from `hint`, as `CKDTree::nearest_neighbor_warm` does. The point
+     is reported by its payload index. */
+    pub fn nearest_neighbor_warm<'t, M: Metric>(&'t self, point: &[f32; K], metric: &M, hint: Option<NearestHint>) -> Option<ApproxNeighbor<'t, usize>> {
+        return self.nearest_neighbor_filtered_warm(point, metric, hint, |_| true);
+    }
+
+    /** Same as `nearest_neighbor_warm`, but only points whose payload index `predicate` accepts are candidates. A hinted
+     point the predicate rejects is ignored. */
+    pub fn nearest_neighbor_filtered_warm<'t, M: Metric>(&'t self, point: &[f32; K], metric: &M, hint: Option<NearestHint>, predicate: impl Fn(usize) -> bool) -> Option<ApproxNeighbor<'t, usize>> {
+        let mut trace: QueryTrace = QueryTrace::default();
+        return self.search_nearest_from(point, hint.map(|h| h.0), 0f32, metric, |idx| predicate(self.m_payloads[idx]), &mut trace)
+            .map(|(idx, dist)| ApproxNeighbor { m_point: &self.m_payloads[idx], m_dist: dist, m_visited: trace.m_visited, m_node: idx });
+    }
+
+    /** Same as `nearest_neighbor`, but also returns a `QueryTrace` of the work the search did. */
+    pub fn nearest_neighbor_traced<M: Metric>(&self, point: &[f32; K], metric: &M) -> (Option<(usize, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::default();
+        let found = self.search_nearest(point, metric, |_| true, &mut trace).map(|(idx, dist)| (self.m_payloads[idx], dist));
+        return (found, trace);
+    }
+
+    /** Same as `nearest_neighbor_traced`, but the trace also logs every step of the search, as `CKDTree::nearest_neighbor_logged`
+     does. Events name nodes by their index; `payload_of` turns them into payload indices. */
+    pub fn nearest_neighbor_logged<M: Metric>(&self, point: &[f32; K], metric: &M) -> (Option<(usize, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::logging();
+        let found = self.search_nearest(point, metric, |_| true, &mut trace).map(|(idx, dist)| (self.m_payloads[idx], dist));
+        return (found, trace);
+    }
+
+    /** Find the nearest neighbor to `point` among only the points whose payload index `predicate` accepts. */
+    pub fn nearest_neighbor_filtered<M: Metric>(&self, point: &[f32; K], metric: &M, predicate: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        return self.search_nearest(point, metric, |idx| predicate(self.m_payloads[idx]), &mut QueryTrace::default())
+            .map(|(idx, dist)| (self.m_payloads[idx], dist));
+    }
+
+    /** Answers `nearest_neighbor` for every point in `points` over `threads` scoped worker threads, in the same order as `points`. */
+    pub fn nearest_batch<M: Metric + Sync>(&self, points: &[[f32; K]], metric: &M, threads: usize) -> Vec<Option<(usize, f32)>> {
+        return run_batch(points, threads, |point: &[f32; K]| self.nearest_neighbor(point, metric));
+    }
+
+    /** Find the `k` nearest neighbors to `point`, returned as payload indices with their distances, closest first. */
+    pub fn k_nearest<M: Metric>(&self, point: &[f32; K], k: usize, metric: &M) -> Vec<(usize, f32)> {
+        return self.k_nearest_traced(point, k, metric).0;
+    }
+
+    /** Same as `k_nearest`, but also returns a `QueryTrace` of the work the search did. */
+    pub fn k_nearest_traced<M: Metric>(&self, point: &[f32; K], k: usize, metric: &M) -> (Vec<(usize, f32)>, QueryTrace) {
+        let mut trace: QueryTrace = QueryTrace::default();
+        let found = self.search_k_nearest(point, k, metric, &mut trace).into_iter().map(|(idx, dist)| (self.m_payloads[idx], dist)).collect();
+        return (found, trace);
+    }
+
+    /** Find every point within `radius` of `point`, returned as payload indices with their distances, closest first. */
+    pub fn within_radius<M: Metric>(&self, point: &[f32; K], radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        return self.search_radius(point, radius, metric).into_iter().map(|(idx, dist)| (self.m_payloads[idx], dist)).collect();
+    }
+
+    /** Find every point inside the axis-aligned box `bounds`, returned as payload indices in traversal order. */
+    pub fn range_search(&self, bounds: &AxisBox) -> Vec<usize> {
+        return self.search_range(bounds).into_iter().map(|idx| self.m_payloads[idx]).collect();
+    }
+
+    /** Find every point whose distance to `point` lies within `tolerance` of the nearest neighbor's, as
+     `CKDTree::nearest_ties` does. The points are reported by payload index, in increasing order of it. */
+    pub fn nearest_ties<M: Metric>(&self, point: &[f32; K], tolerance: f32, metric: &M) -> Vec<(usize, f32)> {
+        return self.nearest_ties_filtered_warm(point, tolerance, metric, None, |_| true).into_iter().map(|tie| (*tie.point(), tie.distance())).collect();
+    }
+
+    /** Same as `nearest_ties`, but warm started from `hint` as `nearest_neighbor_warm` is, and only considering points
+     whose payload index `predicate` accepts. Every tie comes back as an `ApproxNeighbor`, all of them sharing the visited
+     count of the one search, so any of them can hint the next query. */
+    pub fn nearest_ties_filtered_warm<'t, M: Metric>(&'t self, point: &[f32; K], tolerance: f32, metric: &M, hint: Option<NearestHint>, predicate: impl Fn(usize) -> bool) -> Vec<ApproxNeighbor<'t, usize>> {
+        assert!(tolerance >= 0f32, "KdTree::nearest_ties tolerance must be greater than or equal to 0.0, you entered {}", tolerance);
+        let mut trace: QueryTrace = QueryTrace::default();
+        let mut ties: Vec<(usize, f32)> = self.search_nearest_ties_from(point, hint.map(|h| h.0), tolerance, metric, |idx| predicate(self.m_payloads[idx]), &mut trace);
+        ties.sort_by_key(|(idx, _)| self.node_rank(*idx));
+        return ties.into_iter().map(|(idx, dist)| ApproxNeighbor { m_point: &self.m_payloads[idx], m_dist: dist, m_visited: trace.m_visited, m_node: idx }).collect();
+    }
+
+    /** Writes the structure of this tree as a Graphviz DOT digraph, as `CKDTree::export_dot` does, labelling every node
+     with `label` of its payload index. */
+    pub fn export_dot(&self, axis_names: &[&str], label: impl Fn(usize) -> String) -> String {
+        return self.write_dot(axis_names, |idx| label(self.m_payloads[idx]));
+    }
+
+    /** Writes the structure of this tree as nested JSON objects, as `CKDTree::export_json` does, labelling every node
+     with `label` of its payload index. */
+    pub fn export_json(&self, axis_names: &[&str], label: impl Fn(usize) -> String) -> String {
+        return self.write_json(Some(0), axis_names, &|idx| label(self.m_payloads[idx]));
+    }
+
+    /** Returns a box enclosing every point of this tree. Removals do not shrink it until the tree is rebuilt. */
+    pub fn bounding_box(&self) -> &AxisBox {
+        return &self.m_bounds;
+    }
+
+    /** Find the point farthest from `point`, as `CKDTree::farthest_neighbor` does, within the box this tree keeps around
+     its points. Returns its payload index along with its distance. */
+    pub fn farthest_neighbor<M: Metric>(&self, point: &[f32; K], metric: &M) -> Option<(usize, f32)> {
+        return self.farthest_neighbor_filtered(point, metric, |_| true);
+    }
+
+    /** Same as `farthest_neighbor`, but only points whose payload index `predicate` accepts are candidates. */
+    pub fn farthest_neighbor_filtered<M: Metric>(&self, point: &[f32; K], metric: &M, predicate: impl Fn(usize) -> bool) -> Option<(usize, f32)> {
+        return self.search_farthest(point, &self.m_bounds, metric, |idx| predicate(self.m_payloads[idx]), &mut QueryTrace::default())
+            .map(|(idx, dist)| (self.m_payloads[idx], dist));
+    }
+
+    /** Returns a `NearestIter` over the payload indices of every point of this tree in increasing distance from `point` under `metric`. */
+    pub fn nearest_iter<'t, M: Metric>(&'t self, point: &'t [f32; K], metric: &'t M) -> NearestIter<'t, Self, [f32; K], M> {
+        return NearestIter::new(self, point, metric);
+    }
+
+    /** Find the `k` points closest to the segment from `a` to `b`, as `CKDTree::nearest_to_segment` does. Returned as
+     (payload index, distance, t) triples, closest first. */
+    pub fn nearest_to_segment(&self, a: &[f32; K], b: &[f32; K], k: usize) -> Vec<(usize, f32, f32)> {
+        return self.nearest_to_segment_filtered(a, b, k, |_| true);
+    }
+
+    /** Same as `nearest_to_segment`, but only points whose payload index `predicate` accepts are candidates. */
+    pub fn nearest_to_segment_filtered(&self, a: &[f32; K], b: &[f32; K], k: usize, predicate: impl Fn(usize) -> bool) -> Vec<(usize, f32, f32)> {
+        return self.search_segment(a, b, k, |idx| predicate(self.m_payloads[idx])).into_iter().map(|(idx, dist, t)| (self.m_payloads[idx], dist, t)).collect();
+    }
+}
+
+impl<const K: usize> SpatialIndex for KdTree<K> {
+    type Point = [f32; K];
+
+    fn index_dimensions(&self) -> usize {
+        return K;
+    }
+
+    fn point_of(&self, id: usize) -> &[f32; K] {
+        return &self.m_coords[id];
+    }
+
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize> {
+        return self.search_range(bounds);
+    }
+}
+
+impl<const K: usize, M: Metric> MetricIndex<M> for KdTree<K> {
+    fn query_nearest(&self, point: &[f32; K], metric: &M) -> Option<(usize, f32)> {
+        return self.search_nearest(point, metric, |_| true, &mut QueryTrace::default());
+    }
+
+    fn query_k_nearest(&self, point: &[f32; K], k: usize, metric: &M) -> Vec<(usize, f32)> {
+        return self.search_k_nearest(point, k, metric, &mut QueryTrace::default());
+    }
+
+    fn query_radius(&self, point: &[f32; K], radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        return self.search_radius(point, radius, metric);
+    }
 }
 
 #[cfg(test)]
@@ -518,12 +2279,17 @@ mod tests {
 
     use super::ContiguousKDTree;
     use super::CKDTree;
-    use super::CKDWrapper;
     use super::TreeIter;
     use super::VisitState;
     use super::TreeOrder;
-
-    use std::ops::Index;
+    use super::AxisBox;
+    use super::SplitRule;
+    use super::KdTree;
+    use super::KdPoint;
+    use super::{NearestHint, NodeList, QueryTrace, SearchEvent};
+    use crate::metric::{Chebyshev, Euclidean, Manhattan, Metric, SquaredEuclidean, WeightedEuclidean};
+
+    use std::rc::Rc;
     use std::sync::Arc;
 
     #[test]
@@ -539,13 +2305,13 @@ mod tests {
     }
 
     #[test]
-    fn wrapper_new_kdtree_test() {
+    fn boxed_kdtree_test() {
         let vec: Vec<f32> = vec![1f32, 10f32];
         let mut tree = CKDTree::new(2);
-        tree.push_wrapped(Box::new(vec));
-        tree.push_wrapped(Box::new(vec![32f32, 22f32]));
-        tree.push_wrapped(Box::new(vec![0f32, 25f32]));
-        tree.push_wrapped(Box::new(vec![11f32, 2f32]));
+        tree.push(Box::new(vec));
+        tree.push(Box::new(vec![32f32, 22f32]));
+        tree.push(Box::new(vec![0f32, 25f32]));
+        tree.push(Box::new(vec![11f32, 2f32]));
 
         println!("{:?}", tree);
     }
@@ -574,11 +2340,15 @@ mod tests {
         }
     }
 
-    impl Index<usize> for Point {
-        type Output = f32;
-        fn index(&self, index: usize) -> &Self::Output {
-            let ways: [&f32; 2] = [&self.0, &self.1];
-            return ways[index];
+    impl KdPoint for Point {
+        type Scalar = f32;
+
+        fn dimensions(&self) -> usize {
+            return 2;
+        }
+
+        fn coord(&self, axis: usize) -> f32 {
+            return [self.0, self.1][axis];
         }
     }
 
@@ -593,30 +2363,607 @@ mod tests {
         // THE TREE
         let mut arc_point_tree: ContiguousKDTree<Arc<Point>> = CKDTree::new(2);
         for item in arc_point_vec.iter() {
-            arc_point_tree.push_wrapped(item.clone());
+            arc_point_tree.push(item.clone());
         }
 
-        // NEAREST NEIGHBOR COMPARISONS TAKE THE UNDERLYING 'WRAPPED' TYPEs
-        let mut point_tree: ContiguousKDTree<CKDWrapper<[f32; 2]>> = CKDTree::new(2);
+        // NEAREST NEIGHBOR QUERIES TAKE ANY KdPoint, NOT ONLY THE TYPE THE TREE HOLDS
+        let mut point_tree: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
         let test_points: [Point; 5] = [Point::new_i32(7, 3), Point::new_i32(-8, 12), Point::new_i32(7, 11), Point::new_i32(5, 4), Point::new_i32(-8, -6)];
         for pt in test_points {
-            let closest: &Point = arc_point_tree.nearest_neighbor(&pt, |p1,p2| p1.distance_squared(p2)).unwrap();
+            let closest: &Arc<Point> = arc_point_tree.nearest_neighbor(&pt, &SquaredEuclidean).unwrap().0;
+            assert_eq!(closest.as_array(), arc_point_tree.nearest_neighbor(&pt.as_array(), &SquaredEuclidean).unwrap().0.as_array());
             println!("input: {:?}, closest: {:?}", pt, closest);
             point_tree.push(pt.as_array()); // normal_tree gets ownership of each point now
         }
 
         for item in arc_point_vec.iter() {
-            let closest: &[f32; 2] = point_tree.nearest_neighbor(&item.as_ref().as_array(), |p1,p2| (p2[1] - p1[1]).powi(2) + (p2[0] - p1[0]).powi(2)).unwrap();
+            let closest: &[f32; 2] = point_tree.nearest_neighbor(&item.as_ref().as_array(), &SquaredEuclidean).unwrap().0;
             println!("input: {:?}, closest: {:?}", item.as_array(), closest);
         }
     }
 
+    #[test]
+    fn k_nearest_test() {
+        let mut tree: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
+        let points: [[f32; 2]; 7] = [[1.0, 1.0], [-1.0, 5.0], [7.0, 11.0], [6.0, 9.0], [3.0, 3.0], [-4.0, 0.0], [2.0, 8.0]];
+        for pt in points { tree.push(pt); }
+
+        let query: [f32; 2] = [2.0, 2.0];
+        let nearest: Vec<(&[f32; 2], f32)> = tree.k_nearest(&query, 3, &Euclidean);
+
+        let mut brute: Vec<([f32; 2], f32)> = points.iter().map(|p| (*p, Euclidean.distance(p, &query, 2))).collect();
+        brute.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        assert_eq!(nearest.len(), 3);
+        for (found, expected) in nearest.iter().zip(brute.iter()) {
+            assert_eq!(*found.0, expected.0);
+        }
+
+        assert_eq!(tree.k_nearest(&query, 20, &Euclidean).len(), points.len());
+        assert!(tree.k_nearest(&query, 0, &Euclidean).is_empty());
+    }
+
+    #[test]
+    fn within_radius_test() {
+        let mut tree: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
+        let points: [[f32; 2]; 7] = [[1.0, 1.0], [-1.0, 5.0], [7.0, 11.0], [6.0, 9.0], [3.0, 3.0], [-4.0, 0.0], [2.0, 8.0]];
+        for pt in points { tree.push(pt); }
+
+        let query: [f32; 2] = [2.0, 2.0];
+        let found: Vec<(&[f32; 2], f32)> = tree.within_radius(&query, 4.5, &Euclidean);
+        let expected: Vec<&[f32; 2]> = points.iter().filter(|p| Euclidean.distance(*p, &query, 2) <= 4.5).collect();
+
+        assert_eq!(found.len(), expected.len());
+        assert!(found.iter().all(|(p, d)| expected.contains(p) && *d <= 4.5));
+        assert!(found.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert!(tree.within_radius(&query, -1.0, &Euclidean).is_empty());
+    }
+
+    #[test]
+    fn range_search_test() {
+        let mut tree: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
+        let points: [[f32; 2]; 8] = [[1.0, 1.0], [-1.0, 5.0], [7.0, 11.0], [6.0, 9.0], [3.0, 3.0], [-4.0, 0.0], [2.0, 8.0], [3.0, 9.0]];
+        for pt in points { tree.push(pt); }
+
+        let bounds: AxisBox = AxisBox::new(2).between(0, 1.0, 6.0).at_least(1, 3.0);
+        let mut found: Vec<[f32; 2]> = tree.range_search(&bounds).into_iter().map(|p| *p).collect();
+        found.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+        assert_eq!(found, vec![[2.0, 8.0], [3.0, 3.0], [3.0, 9.0], [6.0, 9.0]]);
+
+        assert_eq!(tree.range_search(&AxisBox::new(2)).len(), points.len());
+        assert!(tree.range_search(&AxisBox::new(2).at_most(1, -1.0)).is_empty());
+    }
+
+    #[test]
+    fn from_median_test() {
+        let points: Vec<[f32; 2]> = (0..127).map(|i| [i as f32, i as f32]).collect();
+
+        // pushing sorted points one by one degenerates into a linked list
+        let mut pushed: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
+        for pt in points.iter() { pushed.push(*pt); }
+        assert_eq!(pushed.depth(), points.len());
+
+        for rule in [SplitRule::CYCLE_AXES, SplitRule::MAX_VARIANCE] {
+            let balanced: CKDTree<Box<[f32; 2]>> = CKDTree::from_median(2, points.iter().map(|p| Box::new(*p)).collect(), rule);
+            assert_eq!(balanced.size(), points.len());
+            assert!(balanced.depth() <= 10);
+
+            for pt in points.iter() {
+                assert_eq!(balanced.nearest_neighbor(pt, &Euclidean), Some((&Box::new(*pt), 0f32)));
+            }
+
+            // a dimension of 0 is taken from the points themselves
+            let adopted: CKDTree<[f32; 2]> = CKDTree::from_median(0, points.clone(), rule);
+            assert_eq!((adopted.dimensions(), adopted.depth()), (2, balanced.depth()));
+            assert_eq!(adopted.nearest_neighbor(&[40.2f32, 40.0], &Euclidean).map(|(p, _)| *p), Some([40.0, 40.0]));
+        }
+
+        let empty: CKDTree<[f32; 2]> = CKDTree::from_median(0, Vec::new(), SplitRule::CYCLE_AXES);
+        assert_eq!((empty.dimensions(), empty.size()), (0, 0));
+    }
+
+    #[test]
+    fn from_iter_and_extend_test() {
+        let mut tree: CKDTree<Box<[f32; 3]>> = (0..50).map(|i| Box::new([i as f32, (i * 7 % 50) as f32, (50 - i) as f32])).collect();
+        assert_eq!(tree.size(), 50);
+        assert!(tree.depth() <= 6);
+
+        tree.extend((50..100).map(|i| Box::new([i as f32, (i * 7 % 50) as f32 + 100.0, -i as f32])));
+        assert_eq!(tree.size(), 100);
+        assert!(tree.depth() <= 7);
+        assert_eq!(tree.range_search(&AxisBox::new(3).at_least(1, 100.0)).len(), 50);
+
+        let empty: CKDTree<Box<[f32; 3]>> = Vec::new().into_iter().collect();
+        assert_eq!(empty.size(), 0);
+
+        // an empty collected tree adopts the dimension of the first point it is given
+        let mut pushed: CKDTree<[f32; 3]> = Vec::new().into_iter().collect();
+        pushed.push([1.0, 2.0, 3.0]).push([4.0, 5.0, 6.0]).push([0.0, 9.0, 1.0]);
+        assert_eq!(pushed.nearest_neighbor(&[4.0, 5.0, 5.0], &Euclidean), Some((&[4.0, 5.0, 6.0], 1.0)));
+        let mut extended: CKDTree<[f32; 3]> = Vec::new().into_iter().collect();
+        extended.extend((0..10).map(|i| [i as f32, 0.0, -i as f32]));
+        assert_eq!(extended.size(), 10);
+        assert_eq!(extended.range_search(&AxisBox::new(3).at_most(2, -5.0)).len(), 5);
+    }
+
+    #[test]
+    fn remove_test() {
+        let points: Vec<[f32; 2]> = (0..20).map(|i| [i as f32, (i * 7 % 20) as f32]).collect();
+        let mut tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        tree.set_rebuild_ratio(0.5);
+
+        assert!(tree.remove(&[3.0, 1.0]));
+        assert!(!tree.remove(&[3.0, 1.0]));
+        assert!(!tree.remove(&[100.0, 100.0]));
+        assert_eq!(tree.size(), 19);
+        assert_eq!(tree.removed_count(), 1);
+
+        assert_ne!(tree.nearest_neighbor(&[3.0, 1.0], &Euclidean).unwrap().0, &Box::new([3.0, 1.0]));
+        assert!(tree.k_nearest(&[3.0, 1.0], 20, &Euclidean).iter().all(|(p, _)| ***p != [3.0, 1.0]));
+        assert!(tree.within_radius(&[3.0, 1.0], 0.5, &Euclidean).is_empty());
+        assert!(tree.range_search(&AxisBox::new(2).between(0, 3.0, 3.0)).is_empty());
+        assert_eq!(tree.tree_iter(TreeOrder::PRE_ORDER).count(), 19);
+        assert_eq!(tree.iter().count(), 19);
+
+        // the tenth removal reaches half of the nodes and triggers a rebuild that compacts the node list
+        for pt in points.iter().skip(10).take(9) { assert!(tree.remove(pt)); }
+        assert_eq!(tree.removed_count(), 0);
+        assert_eq!(tree.size(), 10);
+        assert!(tree.remove(&points[19]));
+        assert_eq!(tree.removed_count(), 1);
+        assert_eq!(tree.size(), 9);
+        assert_eq!(tree.iter().count(), 9);
+        for pt in points.iter().take(10).filter(|p| **p != [3.0, 1.0]) {
+            assert_eq!(tree.nearest_neighbor(pt, &Euclidean), Some((&Box::new(*pt), 0f32)));
+        }
+    }
+
+    #[test]
+    fn metric_pruning_matches_brute_force_test() {
+        let points: Vec<[f32; 3]> = (0..200).map(|i| [(i * 37 % 101) as f32 * 0.5, (i * 53 % 97) as f32, (i * 11 % 89) as f32 * 0.1]).collect();
+        let tree: CKDTree<Box<[f32; 3]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let queries: [[f32; 3]; 4] = [[0.3, 0.7, 0.2], [25.0, 50.0, 4.0], [49.5, 96.0, 8.8], [-10.0, 120.0, 3.3]];
+
+        fn check<M: Metric>(tree: &CKDTree<Box<[f32; 3]>>, points: &Vec<[f32; 3]>, query: &[f32; 3], metric: &M) {
+            let best: f32 = points.iter().map(|p| metric.distance(p, query, 3)).fold(f32::MAX, f32::min);
+            let (found, dist) = tree.nearest_neighbor(query, metric).unwrap();
+            assert_eq!(dist, best);
+            assert_eq!(metric.distance(found, query, 3), dist);
+
+            let radius: f32 = best * 3f32;
+            let brute_count: usize = points.iter().filter(|p| metric.distance(*p, query, 3) <= radius).count();
+            assert_eq!(tree.within_radius(query, radius, metric).len(), brute_count);
+            assert_eq!(tree.k_nearest(query, 5, metric).last().unwrap().1, {
+                let mut dists: Vec<f32> = points.iter().map(|p| metric.distance(p, query, 3)).collect();
+                dists.sort_by(|a, b| a.total_cmp(b));
+                dists[4]
+            });
+        }
+
+        for query in queries.iter() {
+            check(&tree, &points, query, &Euclidean);
+            check(&tree, &points, query, &SquaredEuclidean);
+            check(&tree, &points, query, &Manhattan);
+            check(&tree, &points, query, &Chebyshev);
+            check(&tree, &points, query, &WeightedEuclidean::new(3, vec![0.5, 2.0, 10.0]));
+        }
+    }
+
+    #[test]
+    fn approx_nearest_test() {
+        let points: Vec<[f32; 2]> = (0..500).map(|i| [(i * 37 % 499) as f32, (i * 91 % 491) as f32]).collect();
+        let tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+
+        let (mut exact_visits, mut approx_visits): (usize, usize) = (0, 0);
+        for query in [[10.5, 20.5], [250.25, 250.75], [480.0, 3.0], [-20.0, 600.0]] {
+            let exact = tree.approx_nearest(&query, 0f32, &Euclidean).unwrap();
+            let approx = tree.approx_nearest(&query, 1f32, &Euclidean).unwrap();
+
+            assert_eq!(tree.nearest_neighbor(&query, &Euclidean), Some((exact.point(), exact.distance())));
+            assert!(approx.distance() <= 2f32 * exact.distance());
+            assert!(approx.visited() <= exact.visited());
+            (exact_visits, approx_visits) = (exact_visits + exact.visited(), approx_visits + approx.visited());
+        }
+
+        assert!(approx_visits < exact_visits);
+        assert!(exact_visits < 4 * points.len());
+    }
+
+    #[test]
+    fn nearest_neighbor_filtered_test() {
+        let points: Vec<[f32; 2]> = (0..100).map(|i| [(i * 37 % 101) as f32, (i * 53 % 97) as f32]).collect();
+        let tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let accept = |p: &[f32; 2]| (p[0] as i32) % 3 == 0 && p[1] > 20.0;
+
+        for query in points.iter().step_by(7).chain([[50.5, 50.5], [-10.0, 200.0]].iter()) {
+            let (found, dist) = tree.nearest_neighbor_filtered(query, &Euclidean, |p| accept(p)).unwrap();
+            let best: f32 = points.iter().filter(|p| accept(p)).map(|p| Euclidean.distance(p, query, 2)).fold(f32::MAX, f32::min);
+            assert!(accept(found));
+            assert_eq!(dist, best);
+        }
+
+        assert_eq!(tree.nearest_neighbor_filtered(&[0.0, 0.0], &Euclidean, |_| false), None);
+    }
+
+    #[test]
+    fn raw_nodes_round_trip_test() {
+        let mut tree: CKDTree<Box<[f32; 2]>> = (0..30).map(|i| Box::new([i as f32, (i * 7 % 30) as f32])).collect();
+        tree.push(Box::new([4.5, 4.5]));
+        tree.remove(&[3.0, 21.0]);
+
+        let raw = tree.raw_nodes().map(|(axis, dirs, removed, p)| (axis, dirs, removed, p.clone())).collect::<Vec<_>>();
+        let restored: CKDTree<Box<[f32; 2]>> = CKDTree::from_raw_nodes(2, tree.split_rule(), raw.clone()).unwrap();
+        assert_eq!(restored.size(), tree.size());
+        assert_eq!(restored.removed_count(), 1);
+        assert!(restored.raw_nodes().zip(tree.raw_nodes()).all(|(n1, n2)| n1 == n2));
+
+        let mut looped = raw.clone();
+        looped[5].1 = [Some(0), None];
+        assert!(CKDTree::from_raw_nodes(2, SplitRule::CYCLE_AXES, looped).is_err());
+
+        let mut orphaned = raw.clone();
+        orphaned[0].1 = [None, None];
+        assert!(CKDTree::from_raw_nodes(2, SplitRule::CYCLE_AXES, orphaned).is_err());
+        assert!(CKDTree::from_raw_nodes(1, SplitRule::CYCLE_AXES, raw).is_err());
+    }
+
+    #[test]
+    fn nearest_batch_test() {
+        let points: Vec<[f32; 2]> = (0..300).map(|i| [(i * 37 % 101) as f32, (i * 53 % 97) as f32]).collect();
+        let tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let queries: Vec<[f32; 2]> = (0..1000).map(|i| [(i % 113) as f32 * 0.9, (i % 89) as f32 * 1.1]).collect();
+
+        let sequential: Vec<Option<(&Box<[f32; 2]>, f32)>> = queries.iter().map(|q| tree.nearest_neighbor(q, &Euclidean)).collect();
+        for threads in [0, 1, 3, 8, 5000] {
+            assert_eq!(tree.nearest_batch(&queries, &Euclidean, threads), sequential);
+        }
+
+        let no_queries: Vec<[f32; 2]> = Vec::new();
+        assert!(tree.nearest_batch(&no_queries, &Euclidean, 4).is_empty());
+    }
+
+    #[test]
+    fn tree_stats_test() {
+        let perfect: CKDTree<Box<[f32; 2]>> = CKDTree::from_median(2, (0..7).map(|i| Box::new([i as f32, (6 - i) as f32])).collect(), SplitRule::CYCLE_AXES);
+        let stats = perfect.stats();
+        assert_eq!((stats.nodes(), stats.depth(), stats.min_leaf_depth(), stats.max_leaf_depth()), (7, 3, 3, 3));
+        assert_eq!((stats.mean_leaf_depth(), stats.balance()), (3f32, 1f32));
+        assert_eq!(stats.axis_splits(), &[1, 2]);
+        assert_eq!(stats.to_json(&["x", "y"]), "{\"nodes\":7,\"removed\":0,\"depth\":3,\"min_leaf_depth\":3,\"mean_leaf_depth\":3,\"max_leaf_depth\":3,\"axis_splits\":{\"x\":1,\"y\":2},\"balance\":1}");
+
+        let mut list: ContiguousKDTree<[f32; 2]> = CKDTree::new(2);
+        for i in 0..7 { list.push([i as f32, i as f32]); }
+        let stats = list.stats();
+        assert_eq!((stats.depth(), stats.min_leaf_depth(), stats.max_leaf_depth()), (7, 7, 7));
+        assert_eq!(stats.balance(), 7f32 / 3f32);
+        assert_eq!(stats.axis_splits(), &[3, 3]);
+
+        assert_eq!(CKDTree::<Box<[f32; 2]>>::new(2).stats().nodes(), 0);
+    }
+
+    #[test]
+    fn query_trace_test() {
+        let points: Vec<[f32; 2]> = (0..500).map(|i| [(i * 37 % 499) as f32, (i * 91 % 491) as f32]).collect();
+        let tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+
+        let (found, trace) = tree.nearest_neighbor_traced(&[100.5, 200.5], &Euclidean);
+        assert_eq!(found, tree.nearest_neighbor(&[100.5, 200.5], &Euclidean));
+        assert_eq!(trace.visited(), tree.approx_nearest(&[100.5, 200.5], 0f32, &Euclidean).unwrap().visited());
+        assert_eq!(trace.distance_evals(), trace.visited());
+        assert!(trace.pruned() > 0);
+        assert!(trace.visited() < points.len() / 4);
+
+        let (k_found, k_trace) = tree.k_nearest_traced(&[100.5, 200.5], 10, &Euclidean);
+        assert_eq!(k_found, tree.k_nearest(&[100.5, 200.5], 10, &Euclidean));
+        assert!(k_trace.visited() >= trace.visited());
+        assert_eq!(k_trace.to_json(), format!("{{\"visited\":{},\"distance_evals\":{},\"pruned\":{}}}", k_trace.visited(), k_trace.distance_evals(), k_trace.pruned()));
+    }
+
+    #[test]
+    fn fixed_kdtree_matches_ckdtree_test() {
+        let points: Vec<[f32; 3]> = (0..300).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32, (i * 71 % 97) as f32]).collect();
+        let fixed: KdTree<3> = KdTree::from_median(&points, SplitRule::MAX_VARIANCE);
+        let boxed: CKDTree<Box<[f32; 3]>> = CKDTree::from_median(3, points.iter().map(|p| Box::new(*p)).collect(), SplitRule::MAX_VARIANCE);
+        assert_eq!((fixed.size(), fixed.depth(), fixed.stats()), (boxed.size(), boxed.depth(), boxed.stats()));
+
+        let queries: [[f32; 3]; 4] = [[50.5, 40.5, 20.5], [0.0, 0.0, 0.0], [100.0, 88.0, 96.0], [-20.0, 130.0, 45.0]];
+        for query in queries.iter() {
+            let (payload, dist) = fixed.nearest_neighbor(query, &Euclidean).unwrap();
+            assert_eq!((&Box::new(points[payload]), dist), boxed.nearest_neighbor(query, &Euclidean).unwrap());
+
+            let fixed_k: Vec<f32> = fixed.k_nearest(query, 7, &Manhattan).into_iter().map(|(_, d)| d).collect();
+            let boxed_k: Vec<f32> = boxed.k_nearest(query, 7, &Manhattan).into_iter().map(|(_, d)| d).collect();
+            assert_eq!(fixed_k, boxed_k);
+
+            let fixed_radius: Vec<[f32; 3]> = fixed.within_radius(query, 25f32, &Euclidean).into_iter().map(|(payload, _)| points[payload]).collect();
+            assert_eq!(fixed_radius.len(), boxed.within_radius(query, 25f32, &Euclidean).len());
+            assert!(fixed_radius.iter().all(|p| Euclidean.distance(p, query, 3) <= 25f32));
+
+            let (filtered, _) = fixed.nearest_neighbor_filtered(query, &Euclidean, |payload| payload % 2 == 0).unwrap();
+            assert_eq!(filtered % 2, 0);
+        }
+
+        let bounds: AxisBox = AxisBox::new(3).between(0, 20.0, 60.0).at_most(2, 30.0);
+        let mut in_range: Vec<usize> = fixed.range_search(&bounds);
+        in_range.sort();
+        assert_eq!(in_range, (0..points.len()).filter(|idx| bounds.contains(&points[*idx])).collect::<Vec<usize>>());
+
+        let batch: Vec<Option<(usize, f32)>> = fixed.nearest_batch(&queries, &Euclidean, 3);
+        assert_eq!(batch, queries.iter().map(|q| fixed.nearest_neighbor(q, &Euclidean)).collect::<Vec<Option<(usize, f32)>>>());
+    }
+
+    #[test]
+    fn nearest_to_segment_test() {
+        let points: Vec<[f32; 2]> = (0..400).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32]).collect();
+        let tree: KdTree<2> = KdTree::from_median(&points, SplitRule::CYCLE_AXES);
+        let (a, b): ([f32; 2], [f32; 2]) = ([10.3, 80.7], [90.1, 5.2]);
+
+        // brute force: distance from every point to the segment
+        let to_segment = |p: &[f32; 2]| {
+            let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+            let t: f32 = (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / (dx * dx + dy * dy)).clamp(0f32, 1f32);
+            return (Euclidean.distance(p, &[a[0] + t * dx, a[1] + t * dy], 2), t);
+        };
+
+        let mut expected: Vec<f32> = points.iter().map(|p| to_segment(p).0).collect();
+        expected.sort_by(|d1, d2| d1.total_cmp(d2));
+
+        let found: Vec<(usize, f32, f32)> = tree.nearest_to_segment(&a, &b, 12);
+        assert_eq!(found.iter().map(|(_, dist, _)| *dist).collect::<Vec<f32>>(), expected[..12].to_vec());
+        assert!(found.iter().all(|(payload, dist, t)| (*dist, *t) == to_segment(&points[*payload])));
+
+        let boxed: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        assert_eq!(boxed.nearest_to_segment(&a, &b, 12).iter().map(|(_, dist, _)| *dist).collect::<Vec<f32>>(), expected[..12].to_vec());
+
+        // a degenerate segment is just a point
+        let at_point: Vec<(usize, f32, f32)> = tree.nearest_to_segment(&a, &a, 3);
+        assert_eq!(at_point.iter().map(|(p, d, _)| (*p, *d)).collect::<Vec<(usize, f32)>>(), tree.k_nearest(&a, 3, &Euclidean));
+        assert!(tree.nearest_to_segment_filtered(&a, &b, 5, |payload| payload % 3 == 0).iter().all(|(payload, _, _)| payload % 3 == 0));
+    }
+
+    #[test]
+    fn search_log_test() {
+        let points: Vec<[f32; 2]> = (0..200).map(|i| [(i * 37 % 199) as f32, (i * 91 % 191) as f32]).collect();
+        let tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let query: [f32; 2] = [100.5, 60.5];
+
+        let (found, trace) = tree.nearest_neighbor_logged(&query, &Euclidean);
+        let (traced, counts) = tree.nearest_neighbor_traced(&query, &Euclidean);
+        assert_eq!(found, traced);
+        assert_eq!((trace.visited(), trace.distance_evals(), trace.pruned()), (counts.visited(), counts.distance_evals(), counts.pruned()));
+        assert!(counts.events().is_empty());
+
+        // the search starts by walking the same path as tree_travel_iter
+        let events: &[SearchEvent] = trace.events();
+        let descent: Vec<&Box<[f32; 2]>> = events.iter().map_while(|e| if let SearchEvent::DESCEND { node } = e { Some(tree.node_point(*node)) } else { None }).collect();
+        assert_eq!(descent, tree.tree_travel_iter(&query).map(|(_, p)| p).collect::<Vec<&Box<[f32; 2]>>>());
+
+        let count = |pred: fn(&SearchEvent) -> bool| events.iter().filter(|e| pred(e)).count();
+        assert_eq!(count(|e| matches!(e, SearchEvent::DESCEND { .. })), trace.visited());
+        assert_eq!(count(|e| matches!(e, SearchEvent::PRUNE { .. })), trace.pruned());
+        assert!(count(|e| matches!(e, SearchEvent::CROSS { .. })) > 0);
+
+        let candidates: Vec<f32> = events.iter().filter_map(|e| if let SearchEvent::CANDIDATE { dist, .. } = e { Some(*dist) } else { None }).collect();
+        assert!(candidates.windows(2).all(|w| w[1] < w[0]));
+        assert_eq!(candidates.last(), found.map(|(_, dist)| dist).as_ref());
+        assert!(events.iter().all(|e| if let SearchEvent::PRUNE { bound, best, .. } = e { bound >= best } else { true }));
+
+        let fixed: KdTree<2> = KdTree::from_median(&[[1.0, 5.0], [3.0, 2.0]], SplitRule::CYCLE_AXES);
+        let (_, fixed_trace) = fixed.nearest_neighbor_logged(&[3.0, 2.0], &Euclidean);
+        assert_eq!(fixed_trace.events()[0].to_json(|idx| format!("unit {}", fixed.payload_of(idx))), "{\"event\":\"DESCEND\",\"node\":0,\"node_label\":\"unit 1\"}");
+        assert_eq!(
+            SearchEvent::PRUNE { node: 0, child: 1, bound: 2.0, best: 1.5 }.to_json(|idx| idx.to_string()),
+            "{\"event\":\"PRUNE\",\"node\":0,\"node_label\":\"0\",\"child\":1,\"child_label\":\"1\",\"bound\":2,\"best\":1.5}"
+        );
+    }
+
+    #[test]
+    fn export_test() {
+        let mut tree: CKDTree<Box<[f32; 2]>> = CKDTree::from_median(2, vec![Box::new([1.0, 5.0]), Box::new([3.0, 2.0]), Box::new([2.0, 8.0])], SplitRule::CYCLE_AXES);
+        let label = |p: &Box<[f32; 2]>| format!("({}, {})", p[0], p[1]);
+        assert_eq!(
+            tree.export_json(&["x", "y"], label),
+            "{\"label\":\"(2, 8)\",\"axis\":\"x\",\"split\":2,\"removed\":false,\"left\":{\"label\":\"(1, 5)\",\"axis\":\"y\",\"split\":5,\"removed\":false,\"left\":null,\"right\":null},\"right\":{\"label\":\"(3, 2)\",\"axis\":\"y\",\"split\":2,\"removed\":false,\"left\":null,\"right\":null}}"
+        );
+
+        tree.set_rebuild_ratio(1f32).remove(&[3.0, 2.0]);
+        let dot: String = tree.export_dot(&["x"], label);
+        assert!(dot.starts_with("digraph kdtree {\n"));
+        assert!(dot.contains("    n0 [label=\"(2, 8)\\nx = 2\"];\n"));
+        assert!(dot.contains("    n2 [label=\"(3, 2)\\n1 = 2\", style=dashed];\n"));
+        assert!(dot.contains("    n0 -> n1 [label=\"<=\"];\n    n0 -> n2 [label=\">\"];\n"));
+
+        let fixed: KdTree<2> = KdTree::from_median(&[[1.0, 5.0]], SplitRule::CYCLE_AXES);
+        assert_eq!(fixed.export_json(&[], |payload| format!("unit {}", payload)), "{\"label\":\"unit 0\",\"axis\":\"0\",\"split\":1,\"removed\":false,\"left\":null,\"right\":null}");
+        assert_eq!(KdTree::<2>::new().export_json(&[], |payload| payload.to_string()), "null");
+
+        // labels are escaped for JSON and for DOT, non-ASCII characters passed through
+        let odd_label = |_: usize| String::from("Tiki \"Young\" チキ\\\t");
+        assert!(fixed.export_json(&[], odd_label).starts_with("{\"label\":\"Tiki \\\"Young\\\" チキ\\\\\\u0009\",\"axis\":\"0\","));
+        assert!(fixed.export_dot(&[], odd_label).contains("    n0 [label=\"Tiki \\\"Young\\\" チキ\\\\\\n0 = 1\"];\n"));
+    }
+
+    #[test]
+    fn nearest_ties_test() {
+        // a grid with every point stored three times, so most queries have several equidistant answers
+        let points: Vec<[f32; 2]> = (0..300).map(|i| [(i % 10) as f32, (i / 10 % 10) as f32]).collect();
+        let boxed: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let fixed: KdTree<2> = KdTree::from_median(&points, SplitRule::MAX_VARIANCE);
+
+        let on_point: Vec<usize> = fixed.nearest_ties(&[4.0, 7.0], 0f32, &Euclidean).into_iter().map(|(payload, _)| payload).collect();
+        assert_eq!(on_point, vec![74, 174, 274]);
+
+        // both trees order ties the same way: by payload, and by place in the node list, which pushing keeps in insertion order
+        fn node_of<P: KdPoint>(tree: &CKDTree<P>, point: &P) -> usize { return (0..tree.node_count()).find(|idx| std::ptr::eq(tree.node_point(*idx), point)).unwrap(); }
+        let mut pushed: CKDTree<[f32; 2]> = CKDTree::new(2);
+        for point in points.iter() { pushed.push(*point); }
+        let pushed_ties: Vec<usize> = pushed.nearest_ties(&[4.0, 7.0], 0f32, &Euclidean).into_iter().map(|(point, _)| node_of(&pushed, point)).collect();
+        assert_eq!(pushed_ties, on_point);
+
+        let between: Vec<(&Box<[f32; 2]>, f32)> = boxed.nearest_ties(&[4.5, 7.5], 1e-5, &Euclidean);
+        assert_eq!(between.len(), 12);
+        assert!(between.iter().all(|(_, dist)| *dist == 0.5f32.sqrt()));
+        assert!(between.windows(2).all(|w| node_of(&boxed, w[0].0) < node_of(&boxed, w[1].0)));
+
+        // a looser tolerance takes in the next ring, a point off the grid has a single nearest
+        assert_eq!(fixed.nearest_ties(&[4.0, 7.0], 1f32, &Manhattan).len(), 15);
+        assert_eq!(fixed.nearest_ties(&[4.1, 7.2], 0f32, &Euclidean).len(), 3);
+
+        // warm starts, even from a hint that is not itself among the ties, give the very same answer
+        let hint = fixed.approx_nearest(&[9.0, 0.0], 0f32, &Euclidean).unwrap().hint();
+        let warm: Vec<usize> = fixed.nearest_ties_filtered_warm(&[4.5, 7.5], 1e-5, &Euclidean, Some(hint), |_| true).iter().map(|tie| *tie.point()).collect();
+        assert_eq!(warm, fixed.nearest_ties(&[4.5, 7.5], 1e-5, &Euclidean).into_iter().map(|(payload, _)| payload).collect::<Vec<usize>>());
+        let odd = fixed.nearest_ties_filtered_warm(&[4.5, 7.5], 1e-5, &Euclidean, Some(hint), |payload| payload % 2 == 1);
+        assert!(odd.len() == 6 && odd.iter().all(|tie| *tie.point() % 2 == 1));
+    }
+
+    #[test]
+    fn farthest_neighbor_test() {
+        let points: Vec<[f32; 3]> = (0..400).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32, (i * 71 % 97) as f32]).collect();
+        let mut tree: CKDTree<Box<[f32; 3]>> = CKDTree::from_median(3, points.iter().map(|p| Box::new(*p)).collect(), SplitRule::MAX_VARIANCE);
+        let fixed: KdTree<3> = KdTree::from_median(&points, SplitRule::CYCLE_AXES);
+        let brute_force = |query: &[f32; 3], metric: &dyn Fn(&[f32; 3], &[f32; 3]) -> f32| points.iter().map(|p| metric(p, query)).fold(0f32, f32::max);
+
+        let bounds: AxisBox = tree.bounding_box();
+        assert!(points.iter().all(|p| bounds.contains(p)) && fixed.bounding_box().contains(&[0f32, 0f32, 0f32]));
+        for query in [[50.5, 40.5, 20.5], [0.0, 0.0, 0.0], [-20.0, 130.0, 45.0], [100.0, 88.0, 96.0]].iter() {
+            assert_eq!(tree.farthest_neighbor(query, &Euclidean, &bounds).unwrap().1, brute_force(query, &|p, q| Euclidean.distance(p, q, 3)));
+            assert_eq!(tree.farthest_neighbor(query, &Manhattan, &bounds).unwrap().1, brute_force(query, &|p, q| Manhattan.distance(p, q, 3)));
+            assert_eq!(fixed.farthest_neighbor(query, &Chebyshev).unwrap().1, brute_force(query, &|p, q| Chebyshev.distance(p, q, 3)));
+
+            let (payload, dist) = fixed.farthest_neighbor(query, &Euclidean).unwrap();
+            assert_eq!(Euclidean.distance(&points[payload], query, 3), dist);
+        }
+
+        // pruned by the corner bound: far fewer nodes than a full scan
+        let mut trace: QueryTrace = QueryTrace::default();
+        tree.search_farthest(&[50.5, 40.5, 20.5], &bounds, &Euclidean, |_| true, &mut trace);
+        assert!(trace.visited() < points.len() / 2);
+
+        // removed and filtered out points are never answers
+        let query: [f32; 3] = [50.5, 40.5, 20.5];
+        let farthest: [f32; 3] = **tree.farthest_neighbor(&query, &Euclidean, &bounds).unwrap().0;
+        tree.set_rebuild_ratio(1f32).remove(&farthest);
+        assert_ne!(**tree.farthest_neighbor(&query, &Euclidean, &bounds).unwrap().0, farthest);
+        let (even, _) = fixed.farthest_neighbor_filtered(&query, &Euclidean, |payload| payload % 2 == 0).unwrap();
+        assert_eq!(even % 2, 0);
+        assert_eq!(KdTree::<3>::new().farthest_neighbor(&query, &Euclidean), None);
+    }
+
+    #[test]
+    fn nearest_iter_test() {
+        let points: Vec<[f32; 2]> = (0..300).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32]).collect();
+        let mut tree: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+        let query: [f32; 2] = [40.5, 33.25];
+
+        let mut expected: Vec<f32> = points.iter().map(|p| Manhattan.distance(p, &query, 2)).collect();
+        expected.sort_by(|d1, d2| d1.total_cmp(d2));
+        assert_eq!(tree.nearest_iter(&query, &Manhattan).map(|(_, dist)| dist).collect::<Vec<f32>>(), expected);
+
+        // lazy: the first few neighbors only need a small part of the tree
+        let mut lazy = tree.nearest_iter(&query, &Euclidean);
+        let first: Vec<f32> = lazy.by_ref().take(3).map(|(_, dist)| dist).collect();
+        assert_eq!(first, tree.k_nearest(&query, 3, &Euclidean).into_iter().map(|(_, dist)| dist).collect::<Vec<f32>>());
+        assert!(lazy.visited() < points.len() / 4);
+
+        let within: usize = tree.nearest_iter(&query, &Euclidean).take_while(|(_, dist)| *dist <= 10f32).count();
+        assert_eq!(within, tree.within_radius(&query, 10f32, &Euclidean).len());
+
+        let first_odd = tree.nearest_iter(&query, &Euclidean).find(|(p, _)| p[0] as usize % 2 == 1).map(|(_, dist)| dist);
+        assert_eq!(first_odd, tree.nearest_neighbor_filtered(&query, &Euclidean, |p| p[0] as usize % 2 == 1).map(|(_, dist)| dist));
+
+        // removed points are never yielded
+        let nearest: [f32; 2] = **tree.nearest_neighbor(&query, &Euclidean).unwrap().0;
+        tree.set_rebuild_ratio(1f32).remove(&nearest);
+        assert!(tree.nearest_iter(&query, &Euclidean).all(|(p, _)| **p != nearest));
+
+        let fixed: KdTree<2> = KdTree::from_median(&points, SplitRule::MAX_VARIANCE);
+        let payloads: Vec<(usize, f32)> = fixed.nearest_iter(&query, &Chebyshev).take(5).collect();
+        assert_eq!(payloads.iter().map(|(_, dist)| *dist).collect::<Vec<f32>>(), fixed.k_nearest(&query, 5, &Chebyshev).into_iter().map(|(_, dist)| dist).collect::<Vec<f32>>());
+        assert!(payloads.iter().all(|(payload, dist)| Chebyshev.distance(&points[*payload], &query, 2) == *dist));
+        assert_eq!(KdTree::<2>::new().nearest_iter(&query, &Euclidean).next(), None);
+
+        // tied points come out in the order nearest_neighbor and nearest_ties break ties in
+        let tied: Vec<[f32; 2]> = (0..60).map(|i| [(i % 4) as f32, (i % 3) as f32]).collect();
+        let (tied_tree, tied_fixed): (CKDTree<[f32; 2]>, KdTree<2>) = (tied.iter().copied().collect(), KdTree::from_median(&tied, SplitRule::MAX_VARIANCE));
+        for query in [[1f32, 1f32], [2.5, 0.5], [-1.0, 3.0]] {
+            let nearest: f32 = tied_tree.nearest_neighbor(&query, &Euclidean).unwrap().1;
+            assert_eq!(tied_tree.nearest_iter(&query, &Euclidean).next(), tied_tree.nearest_neighbor(&query, &Euclidean));
+            assert_eq!(tied_tree.nearest_iter(&query, &Euclidean).take_while(|(_, dist)| *dist == nearest).collect::<Vec<(&[f32; 2], f32)>>(), tied_tree.nearest_ties(&query, 0f32, &Euclidean));
+            assert_eq!(tied_fixed.nearest_iter(&query, &Euclidean).next(), tied_fixed.nearest_neighbor(&query, &Euclidean));
+            assert_eq!(tied_fixed.nearest_iter(&query, &Euclidean).take_while(|(_, dist)| *dist == nearest).collect::<Vec<(usize, f32)>>(), tied_fixed.nearest_ties(&query, 0f32, &Euclidean));
+        }
+    }
+
+    #[test]
+    fn nearest_neighbor_warm_test() {
+        let points: Vec<[f32; 2]> = (0..2000).map(|i| [(i * 37 % 1009) as f32 / 10f32, (i * 53 % 997) as f32 / 10f32]).collect();
+        let fixed: KdTree<2> = KdTree::from_median(&points, SplitRule::CYCLE_AXES);
+        let boxed: CKDTree<Box<[f32; 2]>> = points.iter().map(|p| Box::new(*p)).collect();
+
+        // a dense sweep across the point set, each query warm started from the answer to the previous one
+        let (mut fixed_hint, mut boxed_hint, mut cold_visited, mut warm_visited) = (None, None, 0, 0);
+        for step in 0..500 {
+            let query: [f32; 2] = [5f32 + step as f32 * 0.18, 90f32 - step as f32 * 0.17];
+            let cold = fixed.approx_nearest(&query, 0f32, &Euclidean).unwrap();
+            let warm = fixed.nearest_neighbor_warm(&query, &Euclidean, fixed_hint).unwrap();
+            assert_eq!(warm.distance(), cold.distance());
+            assert!(warm.visited() <= cold.visited());
+            (cold_visited, warm_visited, fixed_hint) = (cold_visited + cold.visited(), warm_visited + warm.visited(), Some(warm.hint()));
+
+            let boxed_warm = boxed.nearest_neighbor_warm(&query, &Euclidean, boxed_hint).unwrap();
+            assert_eq!(boxed_warm.distance(), cold.distance());
+            boxed_hint = Some(boxed_warm.hint());
+        }
+
+        assert!(warm_visited < cold_visited);
+
+        // hints that do not fit the query (or the tree) are harmless
+        let query: [f32; 2] = [50.05, 50.05];
+        let expected: f32 = fixed.nearest_neighbor(&query, &Euclidean).unwrap().1;
+        assert_eq!(fixed.nearest_neighbor_warm(&query, &Euclidean, fixed_hint).unwrap().distance(), expected);
+        let small: KdTree<2> = KdTree::from_median(&points[..3], SplitRule::CYCLE_AXES);
+        assert_eq!(small.nearest_neighbor_warm(&query, &Euclidean, fixed_hint).map(|found| *found.point()), small.nearest_neighbor(&query, &Euclidean).map(|found| found.0));
+        let odd = fixed.nearest_neighbor_filtered_warm(&query, &Euclidean, fixed_hint, |payload| payload % 2 == 1).unwrap();
+        assert_eq!(odd.distance(), fixed.nearest_neighbor_filtered(&query, &Euclidean, |payload| payload % 2 == 1).unwrap().1);
+
+        // exact ties go to the lowest payload, wherever the search starts
+        let twins: Vec<[f32; 2]> = (0..40).map(|i| [(i % 10) as f32, 0f32]).collect();
+        let tied: KdTree<2> = KdTree::from_median(&twins, SplitRule::CYCLE_AXES);
+        assert_eq!(tied.nearest_neighbor(&[3.25, 0.0], &Euclidean), Some((3, 0.25)));
+        for start in [3usize, 13, 23, 33, 4] {
+            let hint: NearestHint = tied.nearest_neighbor_filtered_warm(&twins[start], &Euclidean, None, |payload| payload == start).unwrap().hint();
+            assert_eq!(tied.nearest_neighbor_warm(&[3.25, 0.0], &Euclidean, Some(hint)).map(|found| *found.point()), Some(3));
+        }
+    }
+
+    #[test]
+    fn fixed_kdtree_push_remove_raw_nodes_test() {
+        let mut tree: KdTree<2> = KdTree::new();
+        for i in 0..10 { tree.push([i as f32, (10 - i) as f32], 100 + i); }
+        assert_eq!(tree.depth(), 10);
+        assert_eq!(tree.nearest_neighbor(&[3.2, 6.9], &Euclidean).map(|found| found.0), Some(103));
+
+        let restored: KdTree<2> = KdTree::from_raw_nodes(tree.split_rule(), tree.raw_nodes().map(|(a, d, r, c, p)| (a, d, r, *c, p)).collect()).unwrap();
+        assert!(restored.raw_nodes().eq(tree.raw_nodes()));
+        assert!(KdTree::<2>::from_raw_nodes(SplitRule::CYCLE_AXES, vec![(2, [None, None], false, [0.0, 0.0], 0)]).is_err());
+
+        assert_eq!(tree.remove(&[3.0, 7.0]), Some(103));
+        assert_eq!(tree.remove(&[3.0, 7.0]), None);
+        assert_eq!(tree.nearest_neighbor(&[3.2, 6.9], &Euclidean).map(|found| found.0), Some(104));
+        assert_eq!(tree.size(), 9);
+
+        tree.rebalance();
+        assert_eq!((tree.size(), tree.removed_count(), tree.depth()), (9, 0, 4));
+        assert_eq!(tree.nearest_neighbor(&[3.2, 6.9], &Euclidean).map(|found| found.0), Some(104));
+    }
+
     #[test]
     fn tree_in_order_iterator_test() {
-        let mut foo: ContiguousKDTree<CKDWrapper<[i32; 2]>> = CKDTree::new(2);
+        let mut foo: ContiguousKDTree<[i32; 2]> = CKDTree::new(2);
         foo.push([1, 2]).push([1, 6]).push([0, 5]).push([-1, 7]).push([5, 9]).push([7, 11]);
 
-        let tree_iter: TreeIter<CKDWrapper<[i32; 2]>> = foo.tree_iter(TreeOrder::IN_ORDER);
+        let tree_iter: TreeIter<[i32; 2]> = foo.tree_iter(TreeOrder::IN_ORDER);
         let mut unwraps = 0;
         for item in tree_iter {
             println!("{:?}", item);
@@ -629,10 +2976,10 @@ mod tests {
 
     #[test]
     fn tree_pre_order_iterator_test() {
-        let mut foo: ContiguousKDTree<CKDWrapper<[i32; 2]>> = CKDTree::new(2);
+        let mut foo: ContiguousKDTree<[i32; 2]> = CKDTree::new(2);
         foo.push([1, 2]).push([1, 6]).push([0, 5]).push([-1, 7]).push([5, 9]).push([7, 11]);
 
-        let tree_iter: TreeIter<CKDWrapper<[i32; 2]>> = foo.tree_iter(TreeOrder::PRE_ORDER);
+        let tree_iter: TreeIter<[i32; 2]> = foo.tree_iter(TreeOrder::PRE_ORDER);
         let mut unwraps = 0;
         for item in tree_iter {
             println!("{:?}", item);
@@ -642,17 +2989,87 @@ mod tests {
         println!("we were able to unwrap {} times", unwraps);
     }
 
+    #[test]
+    fn tree_post_and_level_order_iterator_test() {
+        let tree: CKDTree<Box<[f32; 2]>> = CKDTree::from_median(2, (0..7).map(|i| Box::new([i as f32, (6 - i) as f32])).collect(), SplitRule::CYCLE_AXES);
+        let order_of = |order: TreeOrder| tree.tree_iter(order).map(|node| node.index()).collect::<Vec<usize>>();
+
+        // from_median lays the perfect tree out in pre-order: 0 -> (1 -> (2, 3), 4 -> (5, 6))
+        assert_eq!(order_of(TreeOrder::PRE_ORDER), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(order_of(TreeOrder::IN_ORDER), vec![2, 1, 3, 0, 5, 4, 6]);
+        assert_eq!(order_of(TreeOrder::POST_ORDER), vec![2, 3, 1, 5, 6, 4, 0]);
+        assert_eq!(order_of(TreeOrder::LEVEL_ORDER), vec![0, 1, 4, 2, 3, 5, 6]);
+
+        for order in [TreeOrder::IN_ORDER, TreeOrder::PRE_ORDER, TreeOrder::POST_ORDER, TreeOrder::LEVEL_ORDER] {
+            for node in tree.tree_iter(order) {
+                let expected: (usize, Option<usize>) = match node.index() {
+                    0 => (0, None),
+                    1 | 4 => (1, Some(0)),
+                    2 | 3 => (2, Some(1)),
+                    _ => (2, Some(4))
+                };
+
+                assert_eq!((node.depth(), node.parent()), expected);
+                assert_eq!(node.axis(), node.depth() % 2);
+            }
+        }
+    }
+
     #[test]
     fn empty_tree_iter() {
-        let foo: CKDTree<CKDWrapper<[f32; 3]>> = CKDTree::new(3);
+        let foo: CKDTree<[f32; 3]> = CKDTree::new(3);
         for i in foo.tree_iter(TreeOrder::PRE_ORDER) {
             println!("{:?}", i);
         }
     }
 
+    #[test]
+    fn kd_point_test() {
+        let coords: Vec<[f32; 3]> = (0..40).map(|i| [(i * 7 % 40) as f32, (i * 13 % 40) as f32, (i % 5) as f32]).collect();
+        let vectors: CKDTree<na::Vector3<f32>> = coords.iter().map(|c| na::Vector3::new(c[0], c[1], c[2])).collect();
+        let shared: CKDTree<Rc<Vec<f32>>> = coords.iter().map(|c| Rc::new(c.to_vec())).collect();
+        let arrays: CKDTree<[f32; 3]> = coords.iter().copied().collect();
+        assert_eq!((vectors.dimensions(), shared.dimensions()), (3, 3));
+
+        let query: [f32; 3] = [11.5, 20.5, 2.0];
+        let expected: &[f32; 3] = arrays.nearest_neighbor(&query, &Euclidean).unwrap().0;
+        assert_eq!(vectors.nearest_neighbor(&query, &Euclidean).unwrap().0.as_slice(), expected);
+        assert_eq!(shared.nearest_neighbor(&query.to_vec(), &Euclidean).unwrap().0.as_slice(), expected);
+        assert_eq!(shared.nearest_neighbor(&na::Vector3::new(11.5, 20.5, 2.0), &Euclidean).unwrap().0.as_slice(), expected);
+        assert_eq!(arrays.nearest_neighbor(&query[..], &Euclidean).unwrap().0, expected);
+        assert_eq!(Rc::new(query).coord(1), 20.5);
+    }
+
+    #[test]
+    fn scalar_tree_test() {
+        let stats: Vec<[i16; 5]> = (0..60).map(|i| [(i * 7 % 31) as i16, (i * 11 % 29) as i16 - 10, (i * 13 % 23) as i16, (i % 5) as i16, (i * 3 % 17) as i16]).collect();
+        let tree: CKDTree<[i16; 5]> = CKDTree::from_median(5, stats.clone(), SplitRule::MAX_VARIANCE);
+        let query: [i16; 5] = [12, 3, 9, 2, 8];
+
+        let mut brute: Vec<i64> = stats.iter().map(|p| SquaredEuclidean.distance(p, &query, 5)).collect();
+        brute.sort();
+        assert_eq!(tree.nearest_neighbor(&query, &SquaredEuclidean).unwrap().1, brute[0]);
+        assert_eq!(tree.k_nearest(&query, 8, &SquaredEuclidean).iter().map(|(_, dist)| *dist).collect::<Vec<i64>>(), brute[..8].to_vec());
+        assert_eq!(tree.k_nearest(&query, 8, &Manhattan).iter().map(|(p, dist)| (Manhattan.distance(*p, &query, 5), *dist)).filter(|(d1, d2)| d1 != d2).count(), 0);
+
+        // two points exactly as far from the query are tied, not told apart by rounding
+        let mut ties: CKDTree<[u8; 2]> = vec![[10u8, 10], [13, 14], [7, 6], [200, 3]].into_iter().collect();
+        let found: Vec<([u8; 2], i64)> = ties.k_nearest(&[10u8, 10], 3, &SquaredEuclidean).into_iter().map(|(p, dist)| (*p, dist)).collect();
+        assert_eq!((found[0], found[1].1, found[2].1), (([10, 10], 0), 25, 25));
+        assert_eq!(ties.range_search(&AxisBox::new(2).between(0, 7u8, 13).at_most(1, 10)).len(), 2);
+        assert!(ties.remove(&[10u8, 10]));
+        assert_eq!(ties.nearest_neighbor(&[10u8, 10], &Chebyshev).unwrap().1, 4);
+        assert_eq!(ties.bounding_box().contains(&[200u8, 14]), true);
+
+        let floats: CKDTree<[f64; 2]> = (0..50).map(|i| [i as f64 * 0.1, (i * 17 % 50) as f64 * 0.01]).collect();
+        let near: &[f64; 2] = floats.nearest_neighbor(&[2.04, 0.3], &SquaredEuclidean).unwrap().0;
+        let best: f64 = (0..50).map(|i| SquaredEuclidean.distance(&[i as f64 * 0.1, (i * 17 % 50) as f64 * 0.01], &[2.04, 0.3], 2)).fold(f64::MAX, f64::min);
+        assert_eq!(SquaredEuclidean.distance(near, &[2.04, 0.3], 2), best);
+    }
+
     #[test]
     fn tree_travel_iter_test() {
-        let mut foo: ContiguousKDTree<CKDWrapper<[i32; 2]>> = CKDTree::new(2);
+        let mut foo: ContiguousKDTree<[i32; 2]> = CKDTree::new(2);
         foo.push([1, 2]).push([1, 6]).push([0, 5]).push([-1, 7]).push([5, 9]).push([7, 11]);
         for i in foo.tree_travel_iter(&[1, 5]) {
             println!("axis: {}, point: {:?}", i.0, i.1);
@@ -1,12 +1,19 @@
 #[macro_use] extern crate rocket;
 extern crate nalgebra as na;
 
-use feh_manager::{FehKDTree, FehManager};
+use feh_manager::{FehBackend, FehKDTree, FehManager, FehVpMetric, LerpFilter, LERP_STEPS, MAX_LERP_STEPS};
+use kdtree::AxisBox;
 use rocket::fs::NamedFile;
+use rocket::response::status::BadRequest;
 use rocket::State;
 
 mod lerp;
 mod kdtree;
+mod kdforest;
+mod metric;
+mod snapshot;
+mod spatial_index;
+mod vptree;
 mod feh_manager;
 
 #[get("/")]
@@ -55,19 +62,147 @@ fn world() -> &'static str {
     "Hello, world!"
 }
 
-#[get("/?lerp&<startunit>&<endunit>")]
-fn lerp_units<'query, 'storage>(startunit: &'query str, endunit: &'query str, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+#[get("/?lerp&<startunit>&<endunit>&<exclude_endpoints>&<exclude_same_character>&<weapon>")]
+fn lerp_units<'query, 'storage>(startunit: &'query str, endunit: &'query str, exclude_endpoints: Option<bool>, exclude_same_character: Option<bool>, weapon: Option<&'query str>,
+    unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
     let inputs: [&str; 2] = [startunit, endunit];
 
     for unit_input in inputs.into_iter() {
         println!("input -> {}", unit_input);
-        if !unit_pack_state.contains(unit_input) {
+        if !unit_pack_state.contains_unit(unit_input, &tree_state) {
             println!("Not contained!");
             return "[]".to_owned();
         }
     }
     
-    return unit_pack_state.lerp_units_with_dist(startunit, endunit, &tree_state).to_json_names();
+    let filter: LerpFilter = LerpFilter::new()
+        .exclude_endpoints(exclude_endpoints.unwrap_or(false))
+        .exclude_same_character(exclude_same_character.unwrap_or(false))
+        .only_weapon(weapon.map(|w| w.to_owned()));
+
+    return unit_pack_state.lerp_units_with_dist(startunit, endunit, &filter, &tree_state).to_json_names();
+}
+
+// rejects step counts of 0 and past MAX_LERP_STEPS, which a client could otherwise use to make the server allocate and search without bound
+fn check_steps(steps: usize) -> Result<usize, BadRequest<String>> {
+    if steps == 0 { return Err(BadRequest("steps must be at least 1".to_owned())); }
+    if steps > MAX_LERP_STEPS { return Err(BadRequest(format!("steps must be at most {}", MAX_LERP_STEPS))); }
+    return Ok(steps);
+}
+
+#[get("/lerp_path?<startunit>&<endunit>&<steps>")]
+fn lerp_path<'query, 'storage>(startunit: &'query str, endunit: &'query str, steps: Option<usize>, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> Result<String, BadRequest<String>> {
+    let steps: usize = check_steps(steps.unwrap_or(LERP_STEPS))?;
+    if !unit_pack_state.contains_unit(startunit, &tree_state) || !unit_pack_state.contains_unit(endunit, &tree_state) {
+        return Ok("[]".to_owned());
+    }
+
+    return Ok(unit_pack_state.lerp_units(startunit, endunit, steps, &tree_state).to_json_names());
+}
+
+#[get("/lerp_runners_up?<startunit>&<endunit>&<k>")]
+fn lerp_runners_up<'query, 'storage>(startunit: &'query str, endunit: &'query str, k: usize, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if k == 0 || !unit_pack_state.contains_unit(startunit, &tree_state) || !unit_pack_state.contains_unit(endunit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.lerp_units_with_runners_up(startunit, endunit, k, &tree_state).to_json_names();
+}
+
+#[get("/between?<startunit>&<endunit>&<k>")]
+fn units_between<'query, 'storage>(startunit: &'query str, endunit: &'query str, k: usize, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if k == 0 || !unit_pack_state.contains_unit(startunit, &tree_state) || !unit_pack_state.contains_unit(endunit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.units_between(startunit, endunit, k, &tree_state).to_json_names();
+}
+
+#[get("/within?<unit>&<radius>")]
+fn units_within<'query, 'storage>(unit: &'query str, radius: f32, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if !unit_pack_state.contains_unit(unit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.units_within(unit, radius, &tree_state).to_json_names();
+}
+
+#[get("/opposite?<unit>&<same_weapon>")]
+fn most_opposite<'query, 'storage>(unit: &'query str, same_weapon: Option<bool>, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if !unit_pack_state.contains_unit(unit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.most_opposite_to(unit, same_weapon.unwrap_or(false), &tree_state).to_json_names();
+}
+
+#[get("/teammate?<unit>&<team>")]
+fn closest_teammate<'query, 'storage>(unit: &'query str, team: Vec<&'query str>, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if !unit_pack_state.contains_unit(unit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.closest_teammate(unit, &team, &tree_state).to_json_names();
+}
+
+#[get("/range?<hp_min>&<hp_max>&<atk_min>&<atk_max>&<spd_min>&<spd_max>&<def_min>&<def_max>&<res_min>&<res_max>")]
+fn units_in_range<'storage>(hp_min: Option<f32>, hp_max: Option<f32>, atk_min: Option<f32>, atk_max: Option<f32>, spd_min: Option<f32>, spd_max: Option<f32>,
+    def_min: Option<f32>, def_max: Option<f32>, res_min: Option<f32>, res_max: Option<f32>, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    let stat_bounds: [(Option<f32>, Option<f32>); 5] = [(hp_min, hp_max), (atk_min, atk_max), (spd_min, spd_max), (def_min, def_max), (res_min, res_max)];
+
+    let mut bounds: AxisBox = AxisBox::new(5);
+    for (axis, (lower, upper)) in stat_bounds.into_iter().enumerate() {
+        if let Some(lower) = lower { bounds = bounds.at_least(axis, lower); }
+        if let Some(upper) = upper { bounds = bounds.at_most(axis, upper); }
+    }
+
+    return unit_pack_state.units_in_range(&bounds, &tree_state).to_json_names();
+}
+
+#[post("/add_unit?<name>&<character>&<weapon>&<hp>&<atk>&<spd>&<def>&<res>")]
+fn add_unit<'query, 'storage>(name: &'query str, character: &'query str, weapon: Option<&'query str>, hp: f32, atk: f32, spd: f32, def: f32, res: f32,
+    unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> Result<String, BadRequest<&'static str>> {
+    unit_pack_state.add_unit(name, character, weapon.unwrap_or(""), [hp, atk, spd, def, res], &tree_state).map_err(BadRequest)?;
+    return Ok(tree_state.added_count().to_string());
+}
+
+#[get("/diagnostics/tree")]
+fn tree_diagnostics(unit_pack_state: &State<FehManager>, tree_state: &State<FehKDTree>) -> String {
+    return unit_pack_state.tree_stats(&tree_state);
+}
+
+#[get("/diagnostics/tree/export?<format>")]
+fn tree_export_diagnostics<'query, 'storage>(format: Option<&'query str>, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    return unit_pack_state.tree_export(format.unwrap_or("json"), &tree_state);
+}
+
+#[get("/diagnostics/lerp?<startunit>&<endunit>")]
+fn lerp_diagnostics<'query, 'storage>(startunit: &'query str, endunit: &'query str, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> String {
+    if !unit_pack_state.contains_unit(startunit, &tree_state) || !unit_pack_state.contains_unit(endunit, &tree_state) {
+        return "[]".to_owned();
+    }
+
+    return unit_pack_state.lerp_units_traced(startunit, endunit, &tree_state).to_json_names();
+}
+
+#[get("/diagnostics/sweep?<startunit>&<endunit>&<steps>")]
+fn sweep_diagnostics<'query, 'storage>(startunit: &'query str, endunit: &'query str, steps: usize, unit_pack_state: &'storage State<FehManager>, tree_state: &'storage State<FehKDTree>) -> Result<String, BadRequest<String>> {
+    let steps: usize = check_steps(steps)?;
+    if !unit_pack_state.contains_unit(startunit, &tree_state) || !unit_pack_state.contains_unit(endunit, &tree_state) {
+        return Ok("{}".to_owned());
+    }
+
+    return Ok(unit_pack_state.sweep_visits(startunit, endunit, steps, &tree_state));
+}
+
+#[get("/diagnostics/search?<hp>&<atk>&<spd>&<def>&<res>")]
+fn search_diagnostics(hp: f32, atk: f32, spd: f32, def: f32, res: f32, unit_pack_state: &State<FehManager>, tree_state: &State<FehKDTree>) -> String {
+    return unit_pack_state.nearest_search_log(&[hp, atk, spd, def, res], &tree_state);
+}
+
+#[get("/diagnostics/self_check")]
+fn self_check_diagnostics(unit_pack_state: &State<FehManager>, tree_state: &State<FehKDTree>) -> String {
+    return unit_pack_state.self_check_report(&tree_state);
 }
 
 #[get("/all_units")]
@@ -78,11 +213,17 @@ fn  all_units(unit_pack_state: &State<FehManager>) -> String {
 
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
-    let fm: FehManager = FehManager::init("./data/FEH_Unit_List.csv").unwrap();
-    let ft: FehKDTree = FehKDTree::construct_kdtree(&fm);
+    let (fm, mut ft): (FehManager, FehKDTree) = FehManager::init_with_snapshot("./data/FEH_Unit_List.csv", "./data/FEH_Unit_List.snapshot").unwrap();
+    if let Some(backend) = std::env::var("FEH_BACKEND").ok().and_then(|name| FehBackend::from_name(&name)) {
+        ft = ft.with_backend(backend);
+    }
+
+    if let Some(metric) = std::env::var("FEH_VP_METRIC").ok().and_then(|name| FehVpMetric::from_name(&name)) {
+        ft = ft.with_vp_metric(metric);
+    }
 
     let _rocket: rocket::Rocket<rocket::Ignite> = rocket::build()
-        .mount("/", routes![index, world, get_pub_file, get_file, all_units, lerp_units])
+        .mount("/", routes![index, world, get_pub_file, get_file, all_units, lerp_units, lerp_path, lerp_runners_up, units_between, units_within, most_opposite, closest_teammate, units_in_range, add_unit, tree_diagnostics, tree_export_diagnostics, lerp_diagnostics, sweep_diagnostics, search_diagnostics, self_check_diagnostics])
         .manage(fm)
         .manage(ft)
         .launch()
@@ -0,0 +1,310 @@
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+
+use num::{Num, NumCast, Signed, ToPrimitive, Zero};
+
+use crate::kdtree::KdPoint;
+
+/** A number a `CKDTree` keeps coordinates or distances in. Besides arithmetic it must provide `total_order()`, an
+ ordering over every value of the type (NaN included, for floats), which is what sorts points along an axis and ranks
+ candidate neighbors. */
+pub trait KdNumber: Num + NumCast + ToPrimitive + PartialOrd + Copy + Debug + Display {
+    /** Compares `self` with `other`: `total_cmp` for floats, `cmp` for integers. */
+    fn total_order(&self, other: &Self) -> Ordering;
+}
+
+/** A numeric coordinate type that a `CKDTree` can search under any `Metric` over it: f32 or f64, or an integer of at most
+ 16 bits. Distances between points with these coordinates come out in `Dist`, the type itself for floats and i64 for
+ integers. A difference of two 16-bit coordinates squared stays below 2^32, so squared differences summed over as many
+ as 2^31 axes cannot overflow an i64. Integer distances are therefore exact, and two points at the same distance
+ compare equal. */
+pub trait KdScalar: KdNumber {
+    type Dist: KdNumber + Signed;
+
+    /** The smallest value of this type, negative infinity for floats. Marks an axis unbounded from below. */
+    fn lowest() -> Self;
+
+    /** The largest value of this type, infinity for floats. Marks an axis unbounded from above. */
+    fn highest() -> Self;
+
+    /** The absolute difference between `self` and `other`, in distance units. */
+    fn gap(self, other: Self) -> Self::Dist;
+}
+
+macro_rules! kd_number {
+    ($order:ident: $($t:ty),*) => {$(
+        impl KdNumber for $t {
+            fn total_order(&self, other: &$t) -> Ordering {
+                return self.$order(other);
+            }
+        }
+    )*};
+}
+
+macro_rules! kd_scalar_float {
+    ($($t:ty),*) => {$(
+        impl KdScalar for $t {
+            type Dist = $t;
+
+            fn lowest() -> $t {
+                return <$t>::NEG_INFINITY;
+            }
+
+            fn highest() -> $t {
+                return <$t>::INFINITY;
+            }
+
+            fn gap(self, other: $t) -> $t {
+                return (self - other).abs();
+            }
+        }
+    )*};
+}
+
+macro_rules! kd_scalar_int {
+    ($dist:ty: $($t:ty),*) => {$(
+        impl KdScalar for $t {
+            type Dist = $dist;
+
+            fn lowest() -> $t {
+                return <$t>::MIN;
+            }
+
+            fn highest() -> $t {
+                return <$t>::MAX;
+            }
+
+            fn gap(self, other: $t) -> $dist {
+                return (self as $dist - other as $dist).abs();
+            }
+        }
+    )*};
+}
+
+kd_number!(total_cmp: f32, f64);
+kd_number!(cmp: i8, i16, i64, u8, u16);
+kd_scalar_float!(f32, f64);
+kd_scalar_int!(i64: i8, i16, u8, u16);
+
+/** A distance function over `k` dimensional points with `S` coordinates that a `CKDTree` can search with. Besides the
+ distance itself a metric must provide `axis_distance()`, a lower bound on the distance between two points given only
+ how far apart they are along a single axis. Both are expressed in `S::Dist` units, which is what lets the tree decide
+ whether the far side of a splitting plane can still hold something closer than its current best. Metrics that only
+ use subtraction, multiplication and addition (`SquaredEuclidean`, `Manhattan` and `Chebyshev`) are measured over any
+ `KdScalar`, so that distances between integer points carry no rounding at all; the rest only over f32. */
+pub trait Metric<S: KdScalar = f32> {
+    /** The distance between `p1` and `p2`, both of which have `dimensions` axes. */
+    fn distance<A: KdPoint<Scalar = S> + ?Sized, B: KdPoint<Scalar = S> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> S::Dist;
+
+    /** A lower bound on `distance()` for any two points that are `delta` apart along `axis`. */
+    fn axis_distance(&self, axis: usize, delta: S::Dist) -> S::Dist;
+}
+
+/** Marks a metric that is symmetric and obeys the triangle inequality, d(p1, p3) <= d(p1, p2) + d(p2, p3). Indexes that
+ prune by the triangle inequality rather than by splitting planes, such as `VpTree`, only search with these.
+ Pseudometrics, under which distinct points may lie at distance 0 (as with `Angular`), qualify too, since pruning never
+ relies on distinct points lying apart. `SquaredEuclidean` is not one. */
+pub trait TrueMetric: Metric {}
+
+/** The straight line distance, sqrt(sum (p1_i - p2_i)^2). */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Euclidean;
+
+/** The euclidean distance without the square root. Cheaper to compute and gives the same neighbors, but its
+ values (and any radius given alongside it) are squared. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SquaredEuclidean;
+
+/** The taxicab distance, sum |p1_i - p2_i|. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Manhattan;
+
+/** The largest difference along any single axis, max |p1_i - p2_i|. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Chebyshev;
+
+/** The euclidean distance with each axis scaled by a weight, sqrt(sum w_i * (p1_i - p2_i)^2). */
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedEuclidean {
+    m_weights: Vec<f32>
+}
+
+/** The angle in radians between two points seen as vectors from the origin, acos(p1 . p2 / (|p1| |p2|)). Compares the
+ shape of two points whatever their size: points along the same ray lie at distance 0, and the origin is taken to lie a
+ right angle away from every other point. Does not decompose per axis, so it gives a KD-tree nothing to prune with (its
+ `axis_distance()` is always 0), but it is a pseudometric obeying the triangle inequality, which a `VpTree` can search with. */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Angular;
+
+impl WeightedEuclidean {
+    /** Constructs a new `WeightedEuclidean` metric over `dimensions` axes. Weights must be non-negative, one per axis. */
+    pub fn new(dimensions: usize, weights: Vec<f32>) -> Self {
+        assert!(
+            weights.len() == dimensions,
+            "WeightedEuclidean::weights must hold one weight per axis, expected {} but you entered {}",
+            dimensions,
+            weights.len()
+        );
+        assert!(
+            weights.iter().all(|w| *w >= 0f32),
+            "WeightedEuclidean::weights must all be greater than or equal to 0.0, you entered {:?}",
+            weights
+        );
+
+        return WeightedEuclidean { m_weights: weights };
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        return &self.m_weights;
+    }
+}
+
+impl Metric for Euclidean {
+    fn distance<A: KdPoint<Scalar = f32> + ?Sized, B: KdPoint<Scalar = f32> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> f32 {
+        return SquaredEuclidean.distance(p1, p2, dimensions).sqrt();
+    }
+
+    fn axis_distance(&self, _axis: usize, delta: f32) -> f32 {
+        return delta.abs();
+    }
+}
+
+impl<S: KdScalar> Metric<S> for SquaredEuclidean {
+    fn distance<A: KdPoint<Scalar = S> + ?Sized, B: KdPoint<Scalar = S> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> S::Dist {
+        return (0..dimensions).map(|i| p1.coord(i).gap(p2.coord(i))).fold(S::Dist::zero(), |sum, gap| sum + gap * gap);
+    }
+
+    fn axis_distance(&self, _axis: usize, delta: S::Dist) -> S::Dist {
+        return delta * delta;
+    }
+}
+
+impl<S: KdScalar> Metric<S> for Manhattan {
+    fn distance<A: KdPoint<Scalar = S> + ?Sized, B: KdPoint<Scalar = S> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> S::Dist {
+        return (0..dimensions).map(|i| p1.coord(i).gap(p2.coord(i))).fold(S::Dist::zero(), |sum, gap| sum + gap);
+    }
+
+    fn axis_distance(&self, _axis: usize, delta: S::Dist) -> S::Dist {
+        return delta.abs();
+    }
+}
+
+impl<S: KdScalar> Metric<S> for Chebyshev {
+    fn distance<A: KdPoint<Scalar = S> + ?Sized, B: KdPoint<Scalar = S> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> S::Dist {
+        return (0..dimensions).map(|i| p1.coord(i).gap(p2.coord(i))).fold(S::Dist::zero(), |max, gap| if gap > max { gap } else { max });
+    }
+
+    fn axis_distance(&self, _axis: usize, delta: S::Dist) -> S::Dist {
+        return delta.abs();
+    }
+}
+
+impl Metric for WeightedEuclidean {
+    fn distance<A: KdPoint<Scalar = f32> + ?Sized, B: KdPoint<Scalar = f32> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> f32 {
+        return (0..dimensions).map(|i| self.m_weights[i] * (p1.coord(i) - p2.coord(i)).powi(2)).sum::<f32>().sqrt();
+    }
+
+    fn axis_distance(&self, axis: usize, delta: f32) -> f32 {
+        return self.m_weights[axis].sqrt() * delta.abs();
+    }
+}
+
+impl Metric for Angular {
+    fn distance<A: KdPoint<Scalar = f32> + ?Sized, B: KdPoint<Scalar = f32> + ?Sized>(&self, p1: &A, p2: &B, dimensions: usize) -> f32 {
+        let dot: f32 = (0..dimensions).map(|i| p1.coord(i) * p2.coord(i)).sum();
+        let norms: f32 = (0..dimensions).map(|i| p1.coord(i) * p1.coord(i)).sum::<f32>().sqrt() * (0..dimensions).map(|i| p2.coord(i) * p2.coord(i)).sum::<f32>().sqrt();
+        if norms == 0f32 {
+            let both_origin: bool = (0..dimensions).all(|i| p1.coord(i) == 0f32 && p2.coord(i) == 0f32);
+            return if both_origin { 0f32 } else { std::f32::consts::FRAC_PI_2 };
+        }
+
+        return (dot / norms).clamp(-1f32, 1f32).acos();
+    }
+
+    fn axis_distance(&self, _axis: usize, _delta: f32) -> f32 {
+        return 0f32;
+    }
+}
+
+impl TrueMetric for Euclidean {}
+impl TrueMetric for Manhattan {}
+impl TrueMetric for Chebyshev {}
+impl TrueMetric for WeightedEuclidean {}
+impl TrueMetric for Angular {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Angular, Chebyshev, Euclidean, KdNumber, KdScalar, Manhattan, Metric, SquaredEuclidean, WeightedEuclidean};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn metric_distance_test() {
+        let (p1, p2): ([f32; 3], [f32; 3]) = ([1.0, 2.0, 3.0], [4.0, 6.0, 3.0]);
+
+        assert_eq!(Euclidean.distance(&p1, &p2, 3), 5.0);
+        assert_eq!(SquaredEuclidean.distance(&p1, &p2, 3), 25.0);
+        assert_eq!(Manhattan.distance(&p1, &p2, 3), 7.0);
+        assert_eq!(Chebyshev.distance(&p1, &p2, 3), 4.0);
+        assert_eq!(WeightedEuclidean::new(3, vec![4.0, 1.0, 9.0]).distance(&p1, &p2, 3), 52f32.sqrt());
+        assert_eq!(Angular.distance(&[1.0, 0.0], &[0.0, 2.0], 2), std::f32::consts::FRAC_PI_2);
+        assert!(Angular.distance(&p1, &[2.0, 4.0, 6.0], 3) < 1e-3);
+        assert_eq!(Angular.distance(&[0.0, 0.0], &[3.0, 1.0], 2), std::f32::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    #[should_panic(expected = "one weight per axis")]
+    fn weighted_euclidean_dimensions_test() {
+        WeightedEuclidean::new(5, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn axis_distance_is_lower_bound_test() {
+        let weighted = WeightedEuclidean::new(3, vec![4.0, 0.25, 1.0]);
+        let points: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [3.0, -1.0, 2.0], [-5.0, 4.0, 0.5], [1.0, 1.0, 1.0]];
+
+        for p1 in points.iter() {
+            for p2 in points.iter() {
+                for axis in 0..3 {
+                    let delta: f32 = p1[axis] - p2[axis];
+                    assert!(Euclidean.axis_distance(axis, delta) <= Euclidean.distance(p1, p2, 3));
+                    assert!(Metric::<f32>::axis_distance(&SquaredEuclidean, axis, delta) <= SquaredEuclidean.distance(p1, p2, 3));
+                    assert!(Metric::<f32>::axis_distance(&Manhattan, axis, delta) <= Manhattan.distance(p1, p2, 3));
+                    assert!(Metric::<f32>::axis_distance(&Chebyshev, axis, delta) <= Chebyshev.distance(p1, p2, 3));
+                    assert!(weighted.axis_distance(axis, delta) <= weighted.distance(p1, p2, 3) + 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_distance_test() {
+        let (p1, p2): ([u8; 3], [u8; 3]) = ([1, 200, 3], [4, 6, 3]);
+        assert_eq!(SquaredEuclidean.distance(&p1, &p2, 3), 9 + 194 * 194);
+        assert_eq!(Manhattan.distance(&p1, &p2, 3), 197i64);
+        assert_eq!(Chebyshev.distance(&p1, &p2, 3), 194i64);
+
+        let (q1, q2): ([i16; 2], [i16; 2]) = ([i16::MIN, 0], [i16::MAX, -7]);
+        assert_eq!(SquaredEuclidean.distance(&q1, &q2, 2), 65535 * 65535 + 49);
+        assert_eq!(SquaredEuclidean.distance(&[0.5f64, 1.0], &[2.0, 3.0], 2), 6.25);
+        assert_eq!(SquaredEuclidean.distance(&p1, &p2, 3), SquaredEuclidean.distance(&p1.map(f32::from), &p2.map(f32::from), 3) as i64);
+
+        assert_eq!(KdScalar::gap(3u8, 250u8), 247i64);
+        assert_eq!(<u16 as KdScalar>::lowest(), 0);
+        assert_eq!(<f64 as KdScalar>::highest(), f64::INFINITY);
+        assert_eq!(Metric::<i16>::axis_distance(&SquaredEuclidean, 0, -4i64), 16);
+        assert_eq!(Metric::<u8>::axis_distance(&Manhattan, 0, -4i64), 4);
+    }
+
+    #[test]
+    fn total_order_test() {
+        assert_eq!(f32::NAN.total_order(&f32::INFINITY), Ordering::Greater);
+        assert_eq!((-0f64).total_order(&0f64), Ordering::Less);
+        assert_eq!(3i16.total_order(&-3), Ordering::Greater);
+        assert_eq!(7i64.total_order(&7), Ordering::Equal);
+
+        let mut dists: Vec<f32> = vec![2.0, f32::NAN, -1.0, 0.5];
+        dists.sort_by(|a, b| a.total_order(b));
+        assert_eq!(dists[..3].to_vec(), vec![-1.0, 0.5, 2.0]);
+        assert!(dists[3].is_nan());
+    }
+}
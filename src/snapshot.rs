@@ -0,0 +1,169 @@
+// Versioned, checksummed binary snapshots. A snapshot file is laid out as
+//
+//   magic (8 bytes) | version (u32) | source hash (u64) | payload length (u64) | payload checksum (u64) | payload
+//
+// with every number stored little endian. The source hash fingerprints the file the snapshot was made from, so a
+// snapshot whose source has since changed is reported as stale rather than loaded.
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"FEHSNAP\0";
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 8 + 4 + 8 + 8 + 8;
+const NO_INDEX: u32 = u32::MAX;
+
+/** 64 bit FNV-1a hash. Used both to checksum snapshot payloads and to fingerprint their source files. */
+pub fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+}
+
+/** Builds the payload of a snapshot, then wraps it with its header. */
+pub struct SnapshotWriter {
+    m_payload: Vec<u8>
+}
+
+impl SnapshotWriter {
+    pub fn new() -> Self {
+        return SnapshotWriter { m_payload: Vec::new() };
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> &mut Self {
+        self.m_payload.push(value);
+        return self;
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> &mut Self {
+        self.m_payload.extend_from_slice(&value.to_le_bytes());
+        return self;
+    }
+
+    pub fn write_f32(&mut self, value: f32) -> &mut Self {
+        self.m_payload.extend_from_slice(&value.to_le_bytes());
+        return self;
+    }
+
+    /** Writes a length prefixed UTF-8 string. */
+    pub fn write_str(&mut self, value: &str) -> &mut Self {
+        self.write_u32(value.len() as u32);
+        self.m_payload.extend_from_slice(value.as_bytes());
+        return self;
+    }
+
+    /** Writes an optional index, such as the link to a child node. */
+    pub fn write_index(&mut self, value: Option<usize>) -> &mut Self {
+        return self.write_u32(value.map_or(NO_INDEX, |idx| idx as u32));
+    }
+
+    /** Consumes the writer, returning the complete snapshot file contents. */
+    pub fn finish(self, source_hash: u64) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(HEADER_LEN + self.m_payload.len());
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&source_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.m_payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&fnv1a_64(&self.m_payload).to_le_bytes());
+        bytes.extend_from_slice(&self.m_payload);
+        return bytes;
+    }
+}
+
+/** Reads back the payload of a snapshot made by `SnapshotWriter`, in the order it was written. */
+pub struct SnapshotReader<'a> {
+    m_payload: &'a [u8],
+    m_pos: usize
+}
+
+impl<'a> SnapshotReader<'a> {
+    /** Validates the header of the snapshot in `bytes` and positions a reader at the start of its payload. Fails if the
+     snapshot is not one, was written by another version, was made from a source other than `source_hash`, or if its
+     payload does not match its checksum. */
+    pub fn open(bytes: &'a [u8], source_hash: u64) -> Result<Self, &'static str> {
+        if bytes.len() < HEADER_LEN || &bytes[0..8] != SNAPSHOT_MAGIC { return Err("not a snapshot file"); }
+
+        let header_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        if u32::from_le_bytes(bytes[8..12].try_into().unwrap()) != SNAPSHOT_VERSION { return Err("snapshot version mismatch"); }
+        if header_u64(12) != source_hash { return Err("snapshot is stale"); }
+
+        let payload: &[u8] = &bytes[HEADER_LEN..];
+        if header_u64(20) != payload.len() as u64 || header_u64(28) != fnv1a_64(payload) { return Err("snapshot checksum mismatch"); }
+        return Ok(SnapshotReader { m_payload: payload, m_pos: 0 });
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], &'static str> {
+        if self.m_pos + count > self.m_payload.len() { return Err("snapshot ended unexpectedly"); }
+        self.m_pos += count;
+        return Ok(&self.m_payload[(self.m_pos - count)..self.m_pos]);
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, &'static str> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, &'static str> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, &'static str> {
+        return Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, &'static str> {
+        let len: usize = self.read_u32()? as usize;
+        return std::str::from_utf8(self.take(len)?).map_err(|_| "snapshot holds a string that is not UTF-8");
+    }
+
+    pub fn read_index(&mut self) -> Result<Option<usize>, &'static str> {
+        let value: u32 = self.read_u32()?;
+        return Ok(if value == NO_INDEX { None } else { Some(value as usize) });
+    }
+
+    /** Checks whether the whole payload has been read. */
+    pub fn is_done(&self) -> bool {
+        return self.m_pos == self.m_payload.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SnapshotReader, SnapshotWriter};
+
+    #[test]
+    fn snapshot_round_trip_test() {
+        let mut writer = SnapshotWriter::new();
+        writer.write_u8(7).write_u32(123456).write_f32(-2.5).write_str("Lon'qu").write_index(None).write_index(Some(42));
+        let bytes: Vec<u8> = writer.finish(99);
+
+        let mut reader = SnapshotReader::open(&bytes, 99).unwrap();
+        assert_eq!(reader.read_u8(), Ok(7));
+        assert_eq!(reader.read_u32(), Ok(123456));
+        assert_eq!(reader.read_f32(), Ok(-2.5));
+        assert_eq!(reader.read_str(), Ok("Lon'qu"));
+        assert_eq!(reader.read_index(), Ok(None));
+        assert_eq!(reader.read_index(), Ok(Some(42)));
+        assert!(reader.is_done());
+        assert!(reader.read_u8().is_err());
+    }
+
+    #[test]
+    fn snapshot_rejects_bad_files_test() {
+        let mut writer = SnapshotWriter::new();
+        writer.write_str("Ike");
+        let bytes: Vec<u8> = writer.finish(1);
+
+        assert_eq!(SnapshotReader::open(&bytes, 2).err(), Some("snapshot is stale"));
+        assert_eq!(SnapshotReader::open(&bytes[..10], 1).err(), Some("not a snapshot file"));
+
+        let mut corrupted: Vec<u8> = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        assert_eq!(SnapshotReader::open(&corrupted, 1).err(), Some("snapshot checksum mismatch"));
+
+        let mut other_version: Vec<u8> = bytes.clone();
+        other_version[8] += 1;
+        assert_eq!(SnapshotReader::open(&other_version, 1).err(), Some("snapshot version mismatch"));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::kdtree::{AxisBox, KdPoint};
+use crate::metric::Metric;
+
+/** What every spatial index offers whatever structure it keeps its points in: access to those points and range
+ queries, which need no metric. Points are named by ids the index hands out (a node index for a `CKDTree` or `KdTree`, a
+ position for a `LinearScan` or `VpTree`) and `point_of` turns an id back into its point. */
+pub trait SpatialIndex {
+    type Point: KdPoint<Scalar = f32> + ?Sized;
+
+    /** Returns the number of axes of the indexed points. */
+    fn index_dimensions(&self) -> usize;
+
+    /** Returns the point named by `id`. */
+    fn point_of(&self, id: usize) -> &Self::Point;
+
+    /** The ids of every point inside `bounds`, in no particular order. */
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize>;
+}
+
+/** The nearest, k-nearest and radius queries of a spatial index that can search under the metric `M`. Trees that prune
+ by splitting planes can search under any `Metric`, while a `VpTree` needs a `TrueMetric`, and only the one it was built
+ with. Distances are those of the metric passed to each query. */
+pub trait MetricIndex<M: Metric>: SpatialIndex {
+    /** The id of the point closest to `point`, along with its distance. */
+    fn query_nearest(&self, point: &Self::Point, metric: &M) -> Option<(usize, f32)>;
+
+    /** The ids of the `k` points closest to `point` with their distances, closest first. */
+    fn query_k_nearest(&self, point: &Self::Point, k: usize, metric: &M) -> Vec<(usize, f32)>;
+
+    /** The ids of every point within `radius` of `point` with their distances, closest first. */
+    fn query_radius(&self, point: &Self::Point, radius: f32, metric: &M) -> Vec<(usize, f32)>;
+}
+
+/** The brute force index: keeps its points in a plain list and answers every query by measuring each one of them. Slower
+ than a tree on anything but small point sets, but simple enough to serve as the reference other indexes are checked
+ against. A point's id is its position in the list. */
+#[derive(Debug, Clone)]
+pub struct LinearScan<T> {
+    m_k: usize,
+    m_points: Vec<T>
+}
+
+impl<T: KdPoint<Scalar = f32>> LinearScan<T> {
+    /** Creates a linear scan over `points`, each of which has `dimensions` axes. */
+    pub fn new(dimensions: usize, points: Vec<T>) -> Self {
+        return LinearScan { m_k: dimensions, m_points: points };
+    }
+
+    /** Every point paired with its distance from `point`, sorted from closest to farthest. */
+    fn sorted_by_distance<M: Metric>(&self, point: &T, metric: &M) -> Vec<(usize, f32)> {
+        let mut measured: Vec<(usize, f32)> = self.m_points.iter().enumerate().map(|(id, p)| (id, metric.distance(p, point, self.m_k))).collect();
+        measured.sort_by(|a, b| a.1.total_cmp(&b.1));
+        return measured;
+    }
+}
+
+impl<T: KdPoint<Scalar = f32>> SpatialIndex for LinearScan<T> {
+    type Point = T;
+
+    fn index_dimensions(&self) -> usize {
+        return self.m_k;
+    }
+
+    fn point_of(&self, id: usize) -> &T {
+        return &self.m_points[id];
+    }
+
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize> {
+        return (0..self.m_points.len()).filter(|id| bounds.contains(&self.m_points[*id])).collect();
+    }
+}
+
+impl<T: KdPoint<Scalar = f32>, M: Metric> MetricIndex<M> for LinearScan<T> {
+    fn query_nearest(&self, point: &T, metric: &M) -> Option<(usize, f32)> {
+        return self.m_points.iter().enumerate()
+            .map(|(id, p)| (id, metric.distance(p, point, self.m_k)))
+            .fold(None, |best: Option<(usize, f32)>, cur| if best.is_some_and(|b| b.1 <= cur.1) { best } else { Some(cur) });
+    }
+
+    fn query_k_nearest(&self, point: &T, k: usize, metric: &M) -> Vec<(usize, f32)> {
+        let mut measured: Vec<(usize, f32)> = self.sorted_by_distance(point, metric);
+        measured.truncate(k);
+        return measured;
+    }
+
+    fn query_radius(&self, point: &T, radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        return self.sorted_by_distance(point, metric).into_iter().take_while(|(_, dist)| *dist <= radius).collect();
+    }
+}
+
+/** Runs every query on two indexes over the same points, a primary one and a reference one (typically a `LinearScan`),
+ and answers with the primary's result. Whenever the two disagree, a description of the disagreement is appended to the
+ shared log, which only keeps the latest `LOG_LIMIT` of them. Results are compared by distance, since tied points may legitimately come back in a different order, and
+ range results by the coordinates of the points found. */
+pub struct SelfCheck<'a, A, B> {
+    m_primary: &'a A,
+    m_reference: &'a B,
+    m_log: &'a Mutex<VecDeque<String>>
+}
+
+impl<'a, A, B> SelfCheck<'a, A, B>
+where
+    A: SpatialIndex,
+    B: SpatialIndex<Point = A::Point>, {
+
+    /** The most disagreements the log keeps; once full, each new one pushes out the oldest. */
+    pub const LOG_LIMIT: usize = 256;
+
+    pub fn new(primary: &'a A, reference: &'a B, log: &'a Mutex<VecDeque<String>>) -> Self {
+        return SelfCheck { m_primary: primary, m_reference: reference, m_log: log };
+    }
+
+    /** Appends a disagreement to the log, dropping the oldest one if the log is full. */
+    fn report(&self, disagreement: String) {
+        let mut log = self.m_log.lock().unwrap();
+        if log.len() >= Self::LOG_LIMIT { log.pop_front(); }
+        log.push_back(disagreement);
+    }
+
+    /** Logs a disagreement on `query` unless the distances of both results match. */
+    fn compare_distances(&self, query: &str, primary: &[(usize, f32)], reference: &[(usize, f32)]) {
+        let (primary_dists, reference_dists): (Vec<f32>, Vec<f32>) = (primary.iter().map(|r| r.1).collect(), reference.iter().map(|r| r.1).collect());
+        if primary_dists != reference_dists {
+            self.report(format!("{}: primary found distances {:?} but reference found {:?}", query, primary_dists, reference_dists));
+        }
+    }
+
+    /** The coordinates of the points named by `ids`, sorted so two lists of the same points compare equal. */
+    fn sorted_coords<I: SpatialIndex<Point = A::Point>>(index: &I, ids: &[usize]) -> Vec<Vec<f32>> {
+        let mut coords: Vec<Vec<f32>> = ids.iter().map(|id| (0..index.index_dimensions()).map(|axis| index.point_of(*id).coord(axis)).collect()).collect();
+        coords.sort_by(|c1, c2| c1.iter().zip(c2.iter()).map(|(v1, v2)| v1.total_cmp(v2)).find(|o| o.is_ne()).unwrap_or(std::cmp::Ordering::Equal));
+        return coords;
+    }
+}
+
+impl<'a, A, B> SpatialIndex for SelfCheck<'a, A, B>
+where
+    A: SpatialIndex,
+    B: SpatialIndex<Point = A::Point>, {
+    type Point = A::Point;
+
+    fn index_dimensions(&self) -> usize {
+        return self.m_primary.index_dimensions();
+    }
+
+    fn point_of(&self, id: usize) -> &Self::Point {
+        return self.m_primary.point_of(id);
+    }
+
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize> {
+        let (primary, reference) = (self.m_primary.query_range(bounds), self.m_reference.query_range(bounds));
+        let (primary_coords, reference_coords) = (Self::sorted_coords(self.m_primary, &primary), Self::sorted_coords(self.m_reference, &reference));
+        if primary_coords != reference_coords {
+            self.report(format!("range: primary found {} points but reference found {} (or different ones)", primary.len(), reference.len()));
+        }
+
+        return primary;
+    }
+}
+
+impl<'a, A, B, M> MetricIndex<M> for SelfCheck<'a, A, B>
+where
+    A: MetricIndex<M>,
+    B: MetricIndex<M, Point = A::Point>,
+    M: Metric, {
+
+    fn query_nearest(&self, point: &Self::Point, metric: &M) -> Option<(usize, f32)> {
+        let (primary, reference) = (self.m_primary.query_nearest(point, metric), self.m_reference.query_nearest(point, metric));
+        self.compare_distances("nearest", primary.as_slice(), reference.as_slice());
+        return primary;
+    }
+
+    fn query_k_nearest(&self, point: &Self::Point, k: usize, metric: &M) -> Vec<(usize, f32)> {
+        let (primary, reference) = (self.m_primary.query_k_nearest(point, k, metric), self.m_reference.query_k_nearest(point, k, metric));
+        self.compare_distances("k nearest", &primary, &reference);
+        return primary;
+    }
+
+    fn query_radius(&self, point: &Self::Point, radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        let (primary, reference) = (self.m_primary.query_radius(point, radius, metric), self.m_reference.query_radius(point, radius, metric));
+        self.compare_distances("radius", &primary, &reference);
+        return primary;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinearScan, MetricIndex, SelfCheck, SpatialIndex};
+    use crate::kdtree::{AxisBox, CKDTree, KdTree, SplitRule};
+    use crate::metric::{Euclidean, Manhattan, Metric};
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    #[test]
+    fn backends_agree_test() {
+        let points: Vec<[f32; 3]> = (0..250).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32, (i * 71 % 97) as f32]).collect();
+        let scan: LinearScan<[f32; 3]> = LinearScan::new(3, points.clone());
+        let fixed: KdTree<3> = KdTree::from_median(&points, SplitRule::MAX_VARIANCE);
+        let boxed: CKDTree<[f32; 3]> = points.iter().copied().collect();
+        let log: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+        let (fixed_check, boxed_check) = (SelfCheck::new(&fixed, &scan, &log), SelfCheck::new(&boxed, &scan, &log));
+        let bounds: AxisBox = AxisBox::new(3).between(0, 10.0, 60.0).at_least(2, 40.0);
+        for query in [[50.5, 40.5, 20.5], [0.0, 0.0, 0.0], [-20.0, 130.0, 45.0]].iter() {
+            let (id, dist) = fixed_check.query_nearest(query, &Euclidean).unwrap();
+            assert_eq!(Euclidean.distance(fixed_check.point_of(id), query, 3), dist);
+            boxed_check.query_nearest(query, &Manhattan);
+            fixed_check.query_k_nearest(query, 9, &Manhattan);
+            boxed_check.query_k_nearest(query, 9, &Euclidean);
+            fixed_check.query_radius(query, 30f32, &Euclidean);
+            boxed_check.query_radius(query, 30f32, &Manhattan);
+        }
+
+        assert_eq!(fixed_check.query_range(&bounds).len(), boxed_check.query_range(&bounds).len());
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn self_check_reports_disagreements_test() {
+        let points: Vec<[f32; 2]> = (0..50).map(|i| [i as f32, (50 - i) as f32]).collect();
+        let fixed: KdTree<2> = KdTree::from_median(&points, SplitRule::CYCLE_AXES);
+        let shifted: LinearScan<[f32; 2]> = LinearScan::new(2, points.iter().map(|p| [p[0] + 0.5, p[1]]).collect());
+        let log: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+        let check = SelfCheck::new(&fixed, &shifted, &log);
+
+        let (id, _) = check.query_nearest(&[10.0, 40.0], &Euclidean).unwrap();
+        assert_eq!(check.point_of(id), &[10.0, 40.0]);
+        check.query_range(&AxisBox::new(2).between(0, 5.0, 9.0));
+        check.query_k_nearest(&[10.0, 40.0], 0, &Euclidean);
+
+        {
+            let log = log.lock().unwrap();
+            assert_eq!(log.len(), 2);
+            assert!(log[0].starts_with("nearest:") && log[1].starts_with("range:"));
+        }
+
+        // only the latest disagreements are kept
+        for _ in 0..300 { check.query_range(&AxisBox::new(2).between(0, 5.0, 9.0)); }
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), SelfCheck::<KdTree<2>, LinearScan<[f32; 2]>>::LOG_LIMIT);
+        assert!(log.iter().all(|msg| msg.starts_with("range:")));
+    }
+}
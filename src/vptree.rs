@@ -0,0 +1,205 @@
+use crate::kdtree::{AxisBox, KdPoint};
+use crate::metric::TrueMetric;
+use crate::spatial_index::{MetricIndex, SpatialIndex};
+use std::fmt::Debug;
+
+/** A node of a `VpTree`: a vantage point, and the median distance from it that splits the points below it into those
+ inside the ball it bounds (no farther than `m_radius`) and those outside (no nearer). */
+#[derive(Debug, Clone)]
+struct VpNode {
+    m_id: usize,
+    m_radius: f32,
+    m_dirs: [Option<usize>; 2] // inside, outside
+}
+
+/** A vantage point tree. Where a KD-tree splits space with planes along single axes, a VP-tree splits its points by their
+ distance from a vantage point, and prunes with nothing but the triangle inequality. It can therefore search under any
+ `TrueMetric`, including those that do not decompose per axis (such as `Angular`), though only under the one it was
+ built with. Like a `KdTree`, it keeps its nodes in a flat list rooted at index 0; a point's id is its position in the
+ list the tree was built from. */
+#[derive(Debug, Clone)]
+pub struct VpTree<T, M> {
+    m_k: usize,
+    m_points: Vec<T>,
+    m_nodes: Vec<VpNode>,
+    m_metric: M
+}
+
+impl<T: KdPoint<Scalar = f32>, M: TrueMetric> VpTree<T, M> {
+    /** Builds a balanced `VpTree` over `points`, each of which has `dimensions` axes, measured with `metric`. The vantage
+     point of each subtree is the first of its points, and its radius the median distance of the others from it. */
+    pub fn new(dimensions: usize, points: Vec<T>, metric: M) -> Self {
+        let mut tree: VpTree<T, M> = VpTree { m_k: dimensions, m_points: points, m_nodes: Vec::new(), m_metric: metric };
+        let mut ids: Vec<usize> = (0..tree.m_points.len()).collect();
+        tree.build(&mut ids);
+        return tree;
+    }
+
+    /** Recursively builds the subtree over `ids`, returning the index of its root node. */
+    fn build(&mut self, ids: &mut [usize]) -> Option<usize> {
+        if ids.len() == 0 { return None; }
+        let (node_idx, vantage) = (self.m_nodes.len(), ids[0]);
+        self.m_nodes.push(VpNode { m_id: vantage, m_radius: 0f32, m_dirs: [None, None] });
+        if ids.len() == 1 { return Some(node_idx); }
+
+        let mut measured: Vec<(usize, f32)> = ids[1..].iter().map(|id| (*id, self.m_metric.distance(&self.m_points[vantage], &self.m_points[*id], self.m_k))).collect();
+        let median: usize = (measured.len() - 1) / 2;
+        measured.select_nth_unstable_by(median, |a, b| a.1.total_cmp(&b.1));
+        for (slot, (id, _)) in ids[1..].iter_mut().zip(measured.iter()) { *slot = *id; }
+
+        let (inside, outside) = ids[1..].split_at_mut(median + 1);
+        self.m_nodes[node_idx].m_radius = measured[median].1;
+        self.m_nodes[node_idx].m_dirs = [self.build(inside), self.build(outside)];
+        return Some(node_idx);
+    }
+
+    /** Returns the metric this tree was built with, the only one it can search under. */
+    pub fn metric(&self) -> &M {
+        return &self.m_metric;
+    }
+
+    /** runtime check for whether `metric` is the one this tree was built with */
+    fn check_metric(&self, metric: &M) where M: PartialEq + Debug {
+        assert!(
+            *metric == self.m_metric,
+            "VpTree can only search under the metric it was built with, {:?}, you entered {:?}",
+            self.m_metric,
+            metric
+        );
+    }
+
+    /** Lower bounds on the distance from a point `dist` away from the vantage point of `node` to anything inside and to
+     anything outside its ball, by the triangle inequality. */
+    fn child_bounds(node: &VpNode, dist: f32) -> [f32; 2] {
+        return [(dist - node.m_radius).max(0f32), (node.m_radius - dist).max(0f32)];
+    }
+
+    /** Find the nearest neighbor to `point`. Returns its id along with its distance. */
+    pub fn nearest_neighbor(&self, point: &T) -> Option<(usize, f32)> {
+        return self.k_nearest(point, 1).into_iter().next();
+    }
+
+    /** Find the `k` nearest neighbors to `point`. Subtrees are visited nearest bound first, and skipped once their bound
+     reaches the distance of the worst of the current `k`. Returns at most `k` ids with their distances, closest first. */
+    pub fn k_nearest(&self, point: &T, k: usize) -> Vec<(usize, f32)> {
+        let mut best: Vec<(usize, f32)> = Vec::with_capacity(k + 1);
+        if self.m_nodes.len() == 0 || k == 0 { return best; }
+        let mut vec_stack: Vec<(usize, f32)> = vec![(0, 0f32)];
+
+        while let Some((cur_idx, bound)) = vec_stack.pop() {
+            if best.len() == k && bound >= best[k - 1].1 { continue; }
+            let node: &VpNode = &self.m_nodes[cur_idx];
+            let dist: f32 = self.m_metric.distance(&self.m_points[node.m_id], point, self.m_k);
+            if best.len() < k || dist < best[k - 1].1 {
+                best.insert(best.partition_point(|b| b.1 <= dist), (node.m_id, dist));
+                best.truncate(k);
+            }
+
+            // push the far side first so the side the point lies on is searched first
+            let bounds: [f32; 2] = Self::child_bounds(node, dist);
+            let near: usize = if dist <= node.m_radius { 0 } else { 1 };
+            for side in [1 - near, near] {
+                if let Some(next_idx) = node.m_dirs[side] { vec_stack.push((next_idx, bounds[side])); }
+            }
+        }
+
+        return best;
+    }
+
+    /** Find every point whose distance to `point` is at most `radius`. A subtree is only explored if its bound lies within
+     `radius`. Returns the matching ids paired with their distances, sorted from closest to farthest. */
+    pub fn within_radius(&self, point: &T, radius: f32) -> Vec<(usize, f32)> {
+        let mut matches: Vec<(usize, f32)> = Vec::new();
+        if self.m_nodes.len() == 0 || radius < 0f32 { return matches; }
+        let mut vec_stack: Vec<usize> = vec![0];
+
+        while let Some(cur_idx) = vec_stack.pop() {
+            let node: &VpNode = &self.m_nodes[cur_idx];
+            let dist: f32 = self.m_metric.distance(&self.m_points[node.m_id], point, self.m_k);
+            if dist <= radius { matches.push((node.m_id, dist)); }
+
+            let bounds: [f32; 2] = Self::child_bounds(node, dist);
+            for side in 0..2 {
+                if let Some(next_idx) = node.m_dirs[side].filter(|_| bounds[side] <= radius) { vec_stack.push(next_idx); }
+            }
+        }
+
+        matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+        return matches;
+    }
+}
+
+/** A VP-tree has no notion of axes, so a range query scans every point. */
+impl<T: KdPoint<Scalar = f32>, M: TrueMetric> SpatialIndex for VpTree<T, M> {
+    type Point = T;
+
+    fn index_dimensions(&self) -> usize {
+        return self.m_k;
+    }
+
+    fn point_of(&self, id: usize) -> &T {
+        return &self.m_points[id];
+    }
+
+    fn query_range(&self, bounds: &AxisBox) -> Vec<usize> {
+        return (0..self.m_points.len()).filter(|id| bounds.contains(&self.m_points[*id])).collect();
+    }
+}
+
+/** Searches with the metric the tree was built with, so the metric passed to each query must be that very one. */
+impl<T: KdPoint<Scalar = f32>, M: TrueMetric + PartialEq + Debug> MetricIndex<M> for VpTree<T, M> {
+    fn query_nearest(&self, point: &T, metric: &M) -> Option<(usize, f32)> {
+        self.check_metric(metric);
+        return self.nearest_neighbor(point);
+    }
+
+    fn query_k_nearest(&self, point: &T, k: usize, metric: &M) -> Vec<(usize, f32)> {
+        self.check_metric(metric);
+        return self.k_nearest(point, k);
+    }
+
+    fn query_radius(&self, point: &T, radius: f32, metric: &M) -> Vec<(usize, f32)> {
+        self.check_metric(metric);
+        return self.within_radius(point, radius);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VpTree;
+    use crate::kdtree::AxisBox;
+    use crate::metric::{Angular, Euclidean, Manhattan, WeightedEuclidean};
+    use crate::spatial_index::{LinearScan, MetricIndex, SelfCheck, SpatialIndex};
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    #[test]
+    fn vptree_matches_linear_scan_test() {
+        let points: Vec<[f32; 4]> = (1..300).map(|i| [(i * 37 % 101) as f32, (i * 53 % 89) as f32, (i * 71 % 97) as f32, (i % 13) as f32]).collect();
+        let scan: LinearScan<[f32; 4]> = LinearScan::new(4, points.clone());
+        let (euclidean, manhattan, angular) = (VpTree::new(4, points.clone(), Euclidean), VpTree::new(4, points.clone(), Manhattan), VpTree::new(4, points.clone(), Angular));
+        let log: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+        for query in [[50.5, 40.5, 20.5, 6.0], [0.0, 0.0, 0.0, 0.0], [-20.0, 130.0, 45.0, 3.0], [10.0, 11.0, 12.0, 13.0]].iter() {
+            let (id, dist) = euclidean.query_nearest(query, &Euclidean).unwrap();
+            assert_eq!(scan.query_nearest(query, &Euclidean).unwrap().1, dist);
+            assert_eq!(euclidean.point_of(id), &points[id]);
+
+            SelfCheck::new(&euclidean, &scan, &log).query_k_nearest(query, 7, &Euclidean);
+            SelfCheck::new(&manhattan, &scan, &log).query_radius(query, 45f32, &Manhattan);
+            SelfCheck::new(&angular, &scan, &log).query_k_nearest(query, 5, &Angular);
+            SelfCheck::new(&angular, &scan, &log).query_radius(query, 0.2f32, &Angular);
+        }
+
+        assert!(log.lock().unwrap().is_empty());
+        assert_eq!(euclidean.query_range(&AxisBox::new(4).at_most(3, 0f32)).len(), 23);
+        assert!(VpTree::new(4, Vec::<[f32; 4]>::new(), Euclidean).k_nearest(&[0.0; 4], 3).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "only search under the metric it was built with")]
+    fn vptree_rejects_other_metric_test() {
+        let points: Vec<[f32; 2]> = (0..10).map(|i| [i as f32, 1f32]).collect();
+        VpTree::new(2, points, WeightedEuclidean::new(2, vec![1.0, 2.0])).query_nearest(&[3.0, 0.0], &WeightedEuclidean::new(2, vec![2.0, 1.0]));
+    }
+}